@@ -20,7 +20,8 @@ pub fn main() {
         Path::new("data"),
         Path::new("data\\ne_10m_admin_0_countries_lakes.geojson"),
         6,
-    );
+    )
+    .unwrap();
 
     let mut rng = rand::thread_rng();
     let latlons: Vec<(f64, f64)> = (0..10000000)
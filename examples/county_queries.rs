@@ -0,0 +1,56 @@
+/// This script demonstrates the usage of the pinpointer library for performing point-in-county queries.
+///
+/// The Census Bureau publishes US county borders as TIGER/Line shapefiles rather than GeoJSON, so the
+/// data must be converted once before running this example, e.g. with GDAL:
+/// ```text
+/// curl -O https://www2.census.gov/geo/tiger/TIGER2023/COUNTY/tl_2023_us_county.zip
+/// ogr2ogr -f GeoJSON -t_srs EPSG:4326 data/tl_2023_us_county.geojson /vsizip/tl_2023_us_county.zip
+/// ```
+///
+/// It performs the following steps:
+/// 1. Builds a labeled partition tree for counties based on the converted map data.
+/// 2. Generates a list of random latitude-longitude coordinates within the contiguous United States.
+/// 3. Queries the county FIPS code for each coordinate using the partition tree.
+
+use std::{path::Path, time::Instant};
+
+use geo_types::Point;
+use pinpointer::datasets::load_or_compute_county_label_tree;
+use rand::Rng;
+
+pub fn main() {
+    let counties_path = Path::new("data").join("tl_2023_us_county.geojson");
+    if !counties_path.exists() {
+        println!(
+            "{:?} not found; see the comment at the top of this example for how to create it.",
+            counties_path
+        );
+        return;
+    }
+
+    // build a label tree so we can do point-in-county queries
+    let county_label_tree =
+        load_or_compute_county_label_tree(Path::new("data"), &counties_path, 8).unwrap();
+
+    let mut rng = rand::thread_rng();
+    let latlons: Vec<(f64, f64)> = (0..1000000)
+        .map(|_| (rng.gen_range(24.5..49.5), rng.gen_range(-125.0..-66.9)))
+        .collect();
+
+    // query 1,000,000 county codes
+    let t0 = Instant::now();
+    let mut labels = vec![];
+    for (lat, lon) in latlons.iter() {
+        let label = county_label_tree.label(&Point::new(*lon, *lat));
+        labels.push(label);
+    }
+
+    let duration = t0.elapsed().as_secs_f64();
+
+    println!(
+        "{} point-in-county queries completed in {:.4} seconds ({:.2} queries per second).",
+        latlons.len(),
+        duration,
+        latlons.len() as f64 / duration
+    );
+}
@@ -0,0 +1,55 @@
+/// This script compares the throughput of `label_xy` against `label` with a `Point` for
+/// point-in-country queries.
+///
+/// It performs the following steps:
+/// 1. Downloads the required country map data if it is not already available.
+/// 2. Builds a labeled partition tree for countries based on the downloaded map data.
+/// 3. Generates a list of random longitude-latitude coordinates.
+/// 4. Queries every coordinate with both methods, checks that they agree, and reports their timings.
+
+use std::{path::Path, time::Instant};
+
+use geo_types::Point;
+use pinpointer::datasets::{lazy_download_map_data, load_or_compute_country_label_tree};
+use rand::Rng;
+
+pub fn main() {
+    lazy_download_map_data().unwrap(); // make sure we can access the country maps we need
+
+    let country_label_tree = load_or_compute_country_label_tree(
+        Path::new("data"),
+        &Path::new("data").join("ne_10m_admin_0_countries_lakes.geojson"),
+        6,
+    )
+    .unwrap();
+
+    let mut rng = rand::thread_rng();
+    let lonlats: Vec<(f64, f64)> = (0..10000000)
+        .map(|_| (rng.gen_range(-180.0..180.0), rng.gen_range(-90.0..90.0)))
+        .collect();
+
+    let t0 = Instant::now();
+    let point_labels: Vec<Option<String>> = lonlats
+        .iter()
+        .map(|(lon, lat)| country_label_tree.label(&Point::new(*lon, *lat)))
+        .collect();
+    let point_duration = t0.elapsed().as_secs_f64();
+
+    let t0 = Instant::now();
+    let xy_labels: Vec<Option<String>> = lonlats
+        .iter()
+        .map(|(lon, lat)| country_label_tree.label_xy(*lon, *lat))
+        .collect();
+    let xy_duration = t0.elapsed().as_secs_f64();
+
+    assert_eq!(point_labels, xy_labels);
+    for (name, duration) in [("label", point_duration), ("label_xy", xy_duration)] {
+        println!(
+            "{}: {} queries completed in {:.4} seconds ({:.2} queries per second).",
+            name,
+            lonlats.len(),
+            duration,
+            lonlats.len() as f64 / duration
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Builds a tree from small random polygons and checks that every query agrees with checking
+//! `contains` against all of the polygons.
+//!
+//! Run with `cargo +nightly fuzz run query` from the repository root.
+#![no_main]
+
+use std::collections::HashMap;
+
+use arbitrary::Arbitrary;
+use geo::{Contains, LineString, MultiPolygon, Point, Polygon};
+use libfuzzer_sys::fuzz_target;
+use pinpointer::labeling::{brute_force_label, TreeBuilder};
+
+/// A small polygon. Coordinates are drawn from a coarse grid so that degenerate polygons and
+/// points exactly on polygon edges come up often.
+#[derive(Arbitrary, Debug)]
+enum Shape {
+    Triangle([(i8, i8); 3]),
+    Rect((i8, i8), (i8, i8)),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    max_depth: u8,
+    shapes: Vec<(u8, Shape)>,
+    queries: Vec<(i8, i8)>,
+}
+
+/// Maps a grid coordinate onto the world bbox used by the tree.
+fn coord((x, y): (i8, i8)) -> (f64, f64) {
+    (x as f64 * 1.4, y as f64 * 0.7)
+}
+
+/// Returns whether the point lies on the boundary of a cell of a quadrant tree of the given depth
+/// over the world bbox. `Rect::contains` excludes boundaries, so no cell contains such points and
+/// the tree leaves them unlabeled even when they are inside a polygon.
+fn on_cell_boundary(point: &Point, max_depth: usize) -> bool {
+    let cells = (1u64 << max_depth) as f64;
+    let on_grid = |value: f64, min: f64, size: f64| ((value - min) / size * cells).fract() == 0.0;
+    on_grid(point.x(), -180.0, 360.0) || on_grid(point.y(), -90.0, 180.0)
+}
+
+fn polygon(shape: &Shape) -> Polygon {
+    let vertices: Vec<(f64, f64)> = match shape {
+        Shape::Triangle(vertices) => vertices.iter().map(|&vertex| coord(vertex)).collect(),
+        Shape::Rect(a, b) => {
+            let ((x0, y0), (x1, y1)) = (coord(*a), coord(*b));
+            vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+        }
+    };
+    Polygon::new(LineString::from(vertices), vec![])
+}
+
+fuzz_target!(|input: Input| {
+    let mut polygons: HashMap<u8, Vec<Polygon>> = HashMap::new();
+    for (label, shape) in input.shapes.iter().take(8) {
+        polygons.entry(*label).or_default().push(polygon(shape));
+    }
+    let polygons: HashMap<u8, MultiPolygon> = polygons
+        .into_iter()
+        .map(|(label, polygons)| (label, MultiPolygon::new(polygons)))
+        .collect();
+
+    let max_depth = input.max_depth as usize % 5;
+    let tree = TreeBuilder::new().max_depth(max_depth).build(&polygons);
+
+    for &query in input.queries.iter().take(64) {
+        let (x, y) = coord(query);
+        let point = Point::new(x, y);
+        if on_cell_boundary(&point, max_depth) {
+            continue;
+        }
+        match (tree.label(&point), brute_force_label(&polygons, &point)) {
+            // overlapping regions may legitimately resolve to any of their labels
+            (Some(label), _) => assert!(
+                polygons[&label].contains(&point),
+                "{point:?} was labeled {label}, but is not inside it"
+            ),
+            (None, oracle) => assert!(
+                oracle.is_none(),
+                "{point:?} was unlabeled, but is inside {oracle:?}"
+            ),
+        }
+    }
+});
@@ -1,42 +1,101 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    fs,
+    hash::Hash,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
 
-use geo::{MultiPolygon, Point, Polygon, Rect};
-use geojson::{FeatureCollection, GeoJson};
+use geo::{
+    Area, BoundingRect, CoordsIter, GeodesicArea, InteriorPoint, LineString, MultiLineString,
+    MultiPolygon, Point, Polygon, Rect,
+};
+use geojson::{feature::Id, Feature, FeatureCollection, GeoJson, JsonObject};
 
-use crate::labeling::LabeledPartitionTree;
+use crate::geometry::{buffer_multi_polygon, map_coords_mut, polygonize};
+use crate::labeling::{LabeledPartitionTree, SplitStrategy, TreeBuilder};
 
-use reqwest::blocking::get;
-use std::fs::{File, create_dir};
-use std::io::prelude::*;
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "download")]
+use reqwest::blocking::Client;
+#[cfg(feature = "download")]
+use std::fs::{File, create_dir_all};
+#[cfg(feature = "gzip")]
+use std::io::Read;
+
+/// A file to download, e.g. with `download_map_data_with_client`.
+#[cfg(feature = "download")]
+#[derive(Clone, Debug)]
+pub struct DownloadSpec {
+    /// The URL to download the file from.
+    pub url: String,
+    /// The path to save the file to. Its parent directory is created if necessary.
+    pub path: PathBuf,
+}
+
+/// Returns the specs for the Natural Earth country and province files that
+/// `lazy_download_map_data` downloads to the `data` directory.
+#[cfg(feature = "download")]
+pub fn default_download_specs() -> Vec<DownloadSpec> {
+    let filenames = vec![
+        "ne_10m_admin_0_countries_lakes.geojson",
+        "ne_10m_admin_1_states_provinces_lakes.geojson",
+    ];
+    filenames
+        .into_iter()
+        .map(|filename| DownloadSpec {
+            url: format!(
+                "https://raw.githubusercontent.com/nvkelso/natural-earth-vector/master/geojson/{}",
+                filename
+            ),
+            path: Path::new("data").join(filename),
+        })
+        .collect()
+}
 
 /// Downloads map data lazily if it doesn't exist in the specified directory.
 ///
 /// # Errors
 ///
 /// Returns an error if there is an issue with downloading or writing the files.
+#[cfg(feature = "download")]
 pub fn lazy_download_map_data() -> Result<(), Box<dyn std::error::Error>> {
-    let filenames = vec![
-        "ne_10m_admin_0_countries_lakes.geojson",
-        "ne_10m_admin_1_states_provinces_lakes.geojson",
-    ];
-    for filename in filenames {
-        let data_path = Path::new("data");
-        if !data_path.exists() {
-            create_dir(data_path).unwrap();
+    download_map_data_with_client(&Client::new(), &default_download_specs())
+}
+
+/// Downloads each file that doesn't exist locally using the given HTTP client, e.g. one configured
+/// with a proxy, custom CA certificates, or authentication headers.
+///
+/// # Arguments
+///
+/// * `client` - The client used to download the files.
+/// * `specs` - The files to download.
+///
+/// # Errors
+///
+/// Returns an error if there is an issue with downloading or writing the files, including if the
+/// server responds with an error status.
+#[cfg(feature = "download")]
+pub fn download_map_data_with_client(
+    client: &Client,
+    specs: &[DownloadSpec],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for spec in specs {
+        if let Some(parent) = spec.path.parent() {
+            create_dir_all(parent)?;
         }
 
-        let output_path = data_path.join(filename);
+        let output_path = &spec.path;
         if output_path.exists() {
             println!("Loaded {:?} from local file.", output_path);
         } else {
-            let url = format!(
-                "https://raw.githubusercontent.com/nvkelso/natural-earth-vector/master/geojson/{}",
-                filename
-            );
-            println!("{:?} not found locally. Downloading from {}", output_path, url);
-            let data = get(&url)?.bytes()?;
+            println!("{:?} not found locally. Downloading from {}", output_path, spec.url);
+            let data = client.get(&spec.url).send()?.error_for_status()?.bytes()?;
 
-            let mut file = File::create(&output_path)?;
+            let mut file = File::create(output_path)?;
             file.write_all(&data)?;
             println!("Done.");
         }
@@ -46,45 +105,709 @@ pub fn lazy_download_map_data() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 
+/// A label "property" that makes the loaders label features by their top-level GeoJSON `id`
+/// member rather than one of their properties, for datasets that identify features that way.
+pub const FEATURE_ID_LABEL: &str = "@id";
+
+/// Options controlling how labeled polygons are loaded from GeoJSON.
+#[derive(Clone)]
+pub struct LoadOptions {
+    /// If set, dilates every region by approximately this many degrees after loading, which closes
+    /// small coastal and border gaps at the cost of creating small overlaps between neighbors.
+    pub buffer_degrees: Option<f64>,
+    /// If set, only string property values are used as labels. Otherwise, numbers and booleans
+    /// are converted to their string form (e.g. `42` becomes `"42"`).
+    pub strict_string_labels: bool,
+    /// If set, `load_or_compute_label_tree_with_options` prints a breakdown of the time spent
+    /// building trees that aren't cached.
+    pub verbose: bool,
+    /// Label values that mark features to skip, e.g. the null markers of a dataset. Defaults to
+    /// `["-99"]`, which Natural Earth uses for features without a code.
+    pub skip_values: Vec<String>,
+    /// If set, `load_or_compute_label_tree_with_options` rounds the coordinates of computed trees
+    /// to this many decimal places before caching them, which shrinks the cache at the cost of
+    /// some precision; see `LabeledPartitionTree::round_coordinates`.
+    pub coordinate_decimals: Option<u32>,
+    /// Polygons with areas below this many square degrees, such as zero-area slivers and
+    /// collinear rings, are dropped and the number dropped is printed. Defaults to 0, which keeps
+    /// every polygon.
+    pub min_area: f64,
+    /// What to do with features that have NaN or infinite coordinates. Defaults to
+    /// `NonFinitePolicy::Error`.
+    pub non_finite: NonFinitePolicy,
+    /// If set, applied to every label before it is used as a key (and before it is compared to
+    /// `skip_values`), e.g. to merge variants like `"FR"`, `"fr "`, and `" Fr"` into one region.
+    ///
+    /// Closures can't be compared, so `load_or_compute_label_tree_with_options` only records
+    /// whether a normalizer was used in its cache name; clear the cache after changing it.
+    pub normalize_label: Option<LabelNormalizer>,
+    /// If set, loading fails instead of skipping or warning about anomalies in the data: features
+    /// without the label property, labels that can't be converted to strings, features without
+    /// polygon geometry, and coordinates that are NaN or infinite (regardless of `non_finite`).
+    /// The error lists every problem found. Features with labels in `skip_values` and polygons
+    /// dropped by `min_area` are still skipped, since those are requested explicitly.
+    pub strict: bool,
+    /// If set, features with `LineString` or `MultiLineString` geometry are treated as region
+    /// boundaries and polygonized with `geometry::polygonize`, so that boundary-only datasets can
+    /// be loaded. The number of pieces of linework in each feature that don't close into rings is
+    /// printed (or reported as a problem in strict mode).
+    pub polygonize_lines: bool,
+    /// The order of the coordinates in the source geometry. GeoJSON specifies longitude/latitude
+    /// order, but some sources use latitude/longitude order, which produces a mirrored tree unless
+    /// this is set to `CoordOrder::LatLon`. Defaults to `CoordOrder::LonLat`.
+    pub coordinate_order: CoordOrder,
+    /// The rule `load_or_compute_label_tree_with_options` uses to split cells when building
+    /// trees. It is recorded in the cache, and cached trees built with a different rule are
+    /// rebuilt rather than loaded. Defaults to `SplitStrategy::Quadrants`.
+    pub split: SplitStrategy,
+}
+
+/// The order of the coordinates in source geometry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoordOrder {
+    /// Longitude (x) first, as the GeoJSON spec requires.
+    #[default]
+    LonLat,
+    /// Latitude first; coordinates are swapped while loading.
+    LatLon,
+}
+
+/// A function mapping raw labels to normalized labels; see `LoadOptions::normalize_label`.
+pub type LabelNormalizer = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// What the loaders do with features that have NaN or infinite coordinates, which would otherwise
+/// produce a malformed tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Fail with an error naming the first such feature.
+    #[default]
+    Error,
+    /// Skip such features and print how many were skipped.
+    Skip,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            buffer_degrees: None,
+            strict_string_labels: false,
+            verbose: false,
+            skip_values: vec![String::from("-99")],
+            coordinate_decimals: None,
+            min_area: 0.0,
+            non_finite: NonFinitePolicy::default(),
+            normalize_label: None,
+            strict: false,
+            polygonize_lines: false,
+            coordinate_order: CoordOrder::default(),
+            split: SplitStrategy::default(),
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Applies `normalize_label`, if set, to the given label.
+    fn normalize(&self, name: String) -> String {
+        match &self.normalize_label {
+            Some(normalize) => normalize(&name),
+            None => name,
+        }
+    }
+}
+
+/// A cached tree along with the rule that was used to split its cells, so that trees built with
+/// different rules are never mixed up.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedTree<Tree> {
+    split: SplitStrategy,
+    tree: Tree,
+}
+
 /// Loads labeled polygons from a GeoJSON file and returns them as a HashMap.
+/// Gzipped files (e.g. `.geojson.gz`) are decompressed transparently.
+///
+/// # Arguments
+///
+/// * `path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the polygons.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or if no regions could be loaded from it.
+pub fn load_labeled_collection_polygons(
+    path: &Path,
+    label: &str,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    load_labeled_collection_polygons_with_options(path, label, &LoadOptions::default())
+}
+
+/// Loads labeled polygons from a GeoJSON file using the given options and returns them as a HashMap.
+///
+/// # Arguments
+///
+/// * `path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the polygons.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or if no regions could be loaded from it.
+pub fn load_labeled_collection_polygons_with_options(
+    path: &Path,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    let collection = read_feature_collection(path)?;
+    labeled_polygons_from_feature_collection_with_options(&collection, label, options)
+}
+
+/// Loads labeled polygons from several GeoJSON files, e.g. a dataset sharded by continent, and
+/// merges them into one HashMap.
+///
+/// If several files contain regions with the same label, the label's polygons are the polygons
+/// from all of those files combined into one multipolygon. Overlapping polygons are kept as they
+/// are rather than dissolved into each other, which doesn't affect which points they contain.
+///
+/// # Arguments
+///
+/// * `paths` - The paths to the GeoJSON files.
+/// * `label` - The property to use as the label for the polygons.
+///
+/// # Errors
+///
+/// Returns an error if any of the files cannot be read or parsed, or if no regions could be loaded
+/// from one of them.
+pub fn load_labeled_collection_polygons_multi_files(
+    paths: &[&Path],
+    label: &str,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    let mut merged: HashMap<String, MultiPolygon> = HashMap::new();
+    for path in paths {
+        for (name, polygon) in load_labeled_collection_polygons(path, label)? {
+            merged
+                .entry(name)
+                .or_insert(MultiPolygon::new(vec![]))
+                .0
+                .extend(polygon);
+        }
+    }
+    Ok(merged)
+}
+
+/// Reads and parses a GeoJSON feature collection from a file.
+fn read_feature_collection(path: &Path) -> Result<FeatureCollection, Box<dyn std::error::Error>> {
+    parse_feature_collection(path, fs::read(path)?)
+}
+
+/// Parses a GeoJSON feature collection from the already read contents of a file.
+///
+/// # Arguments
+///
+/// * `path` - The path the contents were read from, used to detect gzipped files and in errors.
+/// * `bytes` - The contents of the file.
+fn parse_feature_collection(
+    path: &Path,
+    bytes: Vec<u8>,
+) -> Result<FeatureCollection, Box<dyn std::error::Error>> {
+    let geojson_str = geojson_string(path, bytes)?;
+    let geojson = geojson_str
+        .parse::<GeoJson>()
+        .map_err(|e| format!("could not parse {} as GeoJSON: {e}", path.display()))?;
+    Ok(FeatureCollection::try_from(geojson)?)
+}
+
+/// Converts the contents of a GeoJSON file to a string, transparently decompressing them if they
+/// are gzipped.
+///
+/// Files are treated as gzipped if they have a `.gz` extension or start with the gzip magic bytes.
+/// Reading gzipped files requires the `gzip` feature.
+fn geojson_string(path: &Path, bytes: Vec<u8>) -> Result<String, Box<dyn std::error::Error>> {
+    let is_gzipped =
+        path.extension().map_or(false, |ext| ext == "gz") || bytes.starts_with(&[0x1f, 0x8b]);
+    if is_gzipped {
+        utf8_string(path, gunzip(path, &bytes)?)
+    } else {
+        utf8_string(path, bytes)
+    }
+}
+
+/// Decompresses the gzipped contents of a file.
+#[cfg(feature = "gzip")]
+fn gunzip(_path: &Path, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut decompressed = vec![];
+    GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Reports that gzipped files can't be read without the `gzip` feature.
+#[cfg(not(feature = "gzip"))]
+fn gunzip(path: &Path, _bytes: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Err(format!(
+        "{} is gzipped, which requires the `gzip` feature",
+        path.display()
+    )
+    .into())
+}
+
+/// Converts the contents of a file to a string, reporting where the first invalid UTF-8 sequence
+/// is if there is one.
+fn utf8_string(path: &Path, bytes: Vec<u8>) -> Result<String, Box<dyn std::error::Error>> {
+    String::from_utf8(bytes).map_err(|e| {
+        let offset = e.utf8_error().valid_up_to();
+        let valid = &e.as_bytes()[..offset];
+        let line = valid.iter().filter(|&&byte| byte == b'\n').count() + 1;
+        let line_start = valid.iter().rposition(|&byte| byte == b'\n').map_or(0, |i| i + 1);
+        let column = String::from_utf8_lossy(&valid[line_start..]).chars().count() + 1;
+        format!(
+            "{} is not valid UTF-8: invalid byte sequence at line {line}, column {column} \
+             (byte offset {offset})",
+            path.display()
+        )
+        .into()
+    })
+}
+
+/// Converts a label property value to a string, or returns `None` if it can't be used as a label.
+///
+/// Null, array, and object values are never used as labels; numbers and booleans are only used if
+/// `strict` is not set.
+fn label_string(value: &serde_json::Value, strict: bool) -> Option<String> {
+    match value {
+        serde_json::Value::String(string) => Some(string.clone()),
+        serde_json::Value::Number(number) if !strict => Some(number.to_string()),
+        serde_json::Value::Bool(boolean) if !strict => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the sorted names of all properties that appear on any feature in a GeoJSON file.
+///
+/// This is useful for finding the right label property for an unfamiliar dataset.
 ///
 /// # Arguments
 ///
 /// * `path` - The path to the GeoJSON file.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn list_properties(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let collection = read_feature_collection(path)?;
+    let names: BTreeSet<String> = collection
+        .features
+        .iter()
+        .flat_map(property_names)
+        .collect();
+    Ok(names.into_iter().collect())
+}
+
+/// Looks up a property of a feature, following dotted paths into nested objects. The
+/// `FEATURE_ID_LABEL` path looks up the feature's top-level `id` instead.
+fn feature_property<'a>(feature: &'a Feature, path: &str) -> Option<Cow<'a, serde_json::Value>> {
+    if path == FEATURE_ID_LABEL {
+        return feature.id.as_ref().map(|id| {
+            Cow::Owned(match id {
+                Id::String(string) => serde_json::Value::String(string.clone()),
+                Id::Number(number) => serde_json::Value::Number(number.clone()),
+            })
+        });
+    }
+    if let Some(value) = feature.property(path) {
+        return Some(Cow::Borrowed(value));
+    }
+    let mut segments = path.split('.');
+    let first = feature.property(segments.next()?)?;
+    segments
+        .try_fold(first, |value, segment| value.get(segment))
+        .map(Cow::Borrowed)
+}
+
+/// Returns the names of the properties of a feature.
+fn property_names(feature: &Feature) -> Vec<String> {
+    feature
+        .properties
+        .iter()
+        .flat_map(|properties| properties.keys().cloned())
+        .collect()
+}
+
+/// Extracts labeled polygons from an already-parsed GeoJSON feature collection.
+///
+/// This is useful when the GeoJSON does not come from a file, e.g. when it was received over HTTP
+/// or read from a database.
+///
+/// # Arguments
+///
+/// * `fc` - The feature collection to extract polygons from.
 /// * `label` - The property to use as the label for the polygons.
-pub fn load_labeled_collection_polygons(path: &Path, label: &str) -> HashMap<String, MultiPolygon> {
-    let geojson_str = fs::read_to_string(path).unwrap();
-    let geojson = geojson_str.parse::<GeoJson>().unwrap();
-    let collection: FeatureCollection = FeatureCollection::try_from(geojson).unwrap();
+///
+/// # Errors
+///
+/// Returns an error if no regions could be loaded from the collection.
+pub fn labeled_polygons_from_feature_collection(
+    fc: &FeatureCollection,
+    label: &str,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    labeled_polygons_from_feature_collection_with_options(fc, label, &LoadOptions::default())
+}
 
+/// Extracts labeled polygons from an already-parsed GeoJSON feature collection using the given
+/// options.
+///
+/// Labels stored in nested objects can be read with a dotted path, e.g. `"codes.iso_a2"` reads
+/// `properties.codes.iso_a2`. A top-level property whose name contains dots takes precedence.
+///
+/// # Arguments
+///
+/// * `fc` - The feature collection to extract polygons from.
+/// * `label` - The property to use as the label for the polygons.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error if no feature has the `label` property, listing the properties of the first
+/// feature so the right one can be picked, or if no regions could be loaded from the collection.
+/// Individual features without the property are skipped.
+pub fn labeled_polygons_from_feature_collection_with_options(
+    fc: &FeatureCollection,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    labeled_polygons_with_sources(fc, label, options).map(|(polygons, _)| polygons)
+}
+
+/// Loads labeled polygons from a GeoJSON file along with, for each label, the indices of the
+/// features that contributed polygons to it.
+///
+/// # Arguments
+///
+/// * `path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the polygons.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or if no regions could be loaded from it.
+pub fn load_labeled_collection_polygons_with_sources(
+    path: &Path,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<(HashMap<String, MultiPolygon>, HashMap<String, Vec<usize>>), Box<dyn std::error::Error>>
+{
+    let collection = read_feature_collection(path)?;
+    labeled_polygons_with_sources(&collection, label, options)
+}
+
+/// Extracts labeled polygons from a GeoJSON feature collection along with, for each label, the
+/// indices of the features in `fc` that contributed polygons to it.
+///
+/// Several features often share a label (e.g. a country made of many islands), so the indices
+/// make it possible to trace a label back to the raw dataset.
+///
+/// # Arguments
+///
+/// * `fc` - The feature collection to extract polygons from.
+/// * `label` - The property to use as the label for the polygons.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error under the same conditions as
+/// [`labeled_polygons_from_feature_collection_with_options`].
+pub fn labeled_polygons_with_sources(
+    fc: &FeatureCollection,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<(HashMap<String, MultiPolygon>, HashMap<String, Vec<usize>>), Box<dyn std::error::Error>>
+{
     let mut labeled_polygons: HashMap<String, Vec<Polygon>> = HashMap::new();
-    collection.features.iter().for_each(|region| {
-        let name = region
-            .property(label)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        if name != "-99" {
-            let geometry = region.geometry.as_ref().unwrap();
+    let mut sources: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, name, polygons) in feature_polygons(fc, label, options)? {
+        sources.entry(name.clone()).or_insert(Vec::new()).push(index);
+        labeled_polygons
+            .entry(name)
+            .or_insert(Vec::new())
+            .extend(polygons);
+    }
+
+    let labeled_polygons = labeled_polygons
+        .into_iter()
+        .map(|(name, polygons)| (name, buffer_polygons(polygons, options)))
+        .collect();
+    Ok((labeled_polygons, sources))
+}
+
+/// Extracts polygons from a GeoJSON feature collection keyed by both their label and the index of
+/// their feature in `fc`, so that trees built from them can tell which feature a point fell in;
+/// see `LabeledPartitionTree::label_with_feature_index`.
+///
+/// Unlike the other loaders, features with the same label are kept separate.
+///
+/// # Arguments
+///
+/// * `fc` - The feature collection to extract polygons from.
+/// * `label` - The property to use as the label for the polygons.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error under the same conditions as
+/// [`labeled_polygons_from_feature_collection_with_options`].
+pub fn labeled_feature_polygons(
+    fc: &FeatureCollection,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<HashMap<(String, usize), MultiPolygon>, Box<dyn std::error::Error>> {
+    Ok(feature_polygons(fc, label, options)?
+        .into_iter()
+        .map(|(index, name, polygons)| ((name, index), buffer_polygons(polygons, options)))
+        .collect())
+}
+
+/// Returns the index, label, and polygons of every feature in the collection that isn't skipped
+/// according to `options`.
+fn feature_polygons(
+    fc: &FeatureCollection,
+    label: &str,
+    options: &LoadOptions,
+) -> Result<Vec<(usize, String, Vec<Polygon>)>, Box<dyn std::error::Error>> {
+    let mut features = vec![];
+    let mut has_label = false;
+    let mut slivers = 0;
+    let mut non_finite = 0;
+    // only collected in strict mode
+    let mut problems: Vec<String> = vec![];
+    for (index, region) in fc.features.iter().enumerate() {
+        let name = match feature_property(region, label) {
+            Some(value) => {
+                has_label = true;
+                match label_string(&value, options.strict_string_labels) {
+                    Some(name) => options.normalize(name),
+                    None => {
+                        if options.strict {
+                            problems.push(format!(
+                                "feature {index} has a {label:?} value that isn't a valid label: \
+                                 {value}"
+                            ));
+                        }
+                        continue;
+                    }
+                }
+            }
+            None => {
+                if options.strict {
+                    problems.push(format!("feature {index} has no {label:?} property"));
+                }
+                continue;
+            }
+        };
+        if !options.skip_values.contains(&name) {
+            // features without geometry (allowed by the GeoJSON spec) have nothing to label
+            let geometry = match region.geometry.as_ref() {
+                Some(geometry) => geometry,
+                None => {
+                    if options.strict {
+                        problems.push(format!("feature {index} ({name:?}) has no geometry"));
+                    }
+                    continue;
+                }
+            };
             let mut polygons: Vec<Polygon> = vec![];
+            let mut is_polygonal = false;
             if let Ok(polygon) = Polygon::try_from(geometry) {
                 polygons = vec![polygon];
+                is_polygonal = true;
             }
             if let Ok(multi_polygon) = MultiPolygon::try_from(geometry) {
-                polygons.extend(multi_polygon)
+                polygons.extend(multi_polygon);
+                is_polygonal = true;
+            }
+            if options.polygonize_lines && !is_polygonal {
+                let lines = MultiLineString::try_from(geometry)
+                    .or_else(|_| LineString::try_from(geometry).map(|line| line.into()));
+                if let Ok(lines) = lines {
+                    let (multi_polygon, unclosed) = polygonize(&lines);
+                    if !unclosed.is_empty() {
+                        let message = format!(
+                            "feature {index} ({name:?}) has {} pieces of linework that don't \
+                             close into rings",
+                            unclosed.len()
+                        );
+                        if options.strict {
+                            problems.push(message);
+                            continue;
+                        }
+                        println!("{message}");
+                    }
+                    polygons.extend(multi_polygon);
+                    is_polygonal = true;
+                }
+            }
+            if options.coordinate_order == CoordOrder::LatLon {
+                let mut multi_polygon = MultiPolygon::new(polygons);
+                map_coords_mut(&mut multi_polygon, |coord| {
+                    std::mem::swap(&mut coord.x, &mut coord.y)
+                });
+                polygons = multi_polygon.0;
+            }
+            if options.strict && !is_polygonal {
+                problems.push(format!(
+                    "feature {index} ({name:?}) has geometry that can't be converted to polygons"
+                ));
+                continue;
+            }
+            let is_finite = polygons
+                .iter()
+                .flat_map(|polygon| polygon.coords_iter())
+                .all(|coord| coord.x.is_finite() && coord.y.is_finite());
+            if !is_finite && options.strict {
+                problems.push(format!(
+                    "feature {index} ({name:?}) has a NaN or infinite coordinate"
+                ));
+                continue;
+            }
+            if !is_finite {
+                match options.non_finite {
+                    NonFinitePolicy::Error => {
+                        return Err(format!(
+                            "feature {index} ({name:?}) has a NaN or infinite coordinate"
+                        )
+                        .into())
+                    }
+                    NonFinitePolicy::Skip => {
+                        non_finite += 1;
+                        continue;
+                    }
+                }
             }
-            labeled_polygons
+            if options.min_area > 0.0 {
+                let count = polygons.len();
+                polygons.retain(|polygon| polygon.unsigned_area() >= options.min_area);
+                slivers += count - polygons.len();
+            }
+            features.push((index, name, polygons));
+        }
+    }
+
+    if !has_label {
+        if let Some(first) = fc.features.first() {
+            return Err(format!(
+                "no feature has the {label:?} property; the first feature has properties {:?}",
+                property_names(first)
+            )
+            .into());
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(format!(
+            "found {} problems in strict mode:\n{}",
+            problems.len(),
+            problems.join("\n")
+        )
+        .into());
+    }
+
+    if non_finite > 0 {
+        println!("Skipped {non_finite} features with NaN or infinite coordinates.");
+    }
+
+    if slivers > 0 {
+        println!(
+            "Dropped {slivers} polygons with areas below {} square degrees.",
+            options.min_area
+        );
+    }
+
+    if features.is_empty() {
+        return Err(format!(
+            "no regions were loaded from {} features using the {label:?} property",
+            fc.features.len()
+        )
+        .into());
+    }
+
+    Ok(features)
+}
+
+/// Combines polygons into a multipolygon, dilating it by `options.buffer_degrees` if set.
+fn buffer_polygons(polygons: Vec<Polygon>, options: &LoadOptions) -> MultiPolygon {
+    let multi_polygon = MultiPolygon::new(polygons);
+    match options.buffer_degrees {
+        Some(distance) => buffer_multi_polygon(&multi_polygon, distance),
+        None => multi_polygon,
+    }
+}
+
+/// Loads the feature properties of each label from a GeoJSON file, e.g. to return a region's name
+/// and other metadata along with its label.
+///
+/// Features are matched to labels the same way as in `load_labeled_collection_polygons`. If
+/// several features have the same label, the properties of the first one are used.
+///
+/// # Arguments
+///
+/// * `path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the features.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn load_label_properties(
+    path: &Path,
+    label: &str,
+) -> Result<HashMap<String, JsonObject>, Box<dyn std::error::Error>> {
+    let collection = read_feature_collection(path)?;
+    let options = LoadOptions::default();
+    let mut properties: HashMap<String, JsonObject> = HashMap::new();
+    for feature in collection.features.iter() {
+        let name = match feature_property(feature, label)
+            .and_then(|value| label_string(&value, options.strict_string_labels))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        if !options.skip_values.contains(&name) {
+            properties
                 .entry(name)
-                .or_insert(Vec::new())
-                .extend(polygons);
+                .or_insert_with(|| feature.properties.clone().unwrap_or_default());
         }
-    });
+    }
+    Ok(properties)
+}
 
-    labeled_polygons
-        .iter()
-        .map(|(name, polygons)| (name.clone(), MultiPolygon::new(polygons.clone())))
-        .collect()
+/// Parses labeled WKB (well-known binary) geometries into a HashMap from labels to polygons, e.g.
+/// to build trees straight from the rows of a PostGIS query.
+///
+/// As with the GeoJSON loaders, polygons with the same label are combined into one multipolygon.
+///
+/// # Arguments
+///
+/// * `items` - The labels and WKB-encoded polygons or multipolygons.
+///
+/// # Errors
+///
+/// Returns an error if any geometry is not valid WKB or is not a polygon or multipolygon.
+#[cfg(feature = "wkb")]
+pub fn labeled_polygons_from_wkb(
+    items: impl Iterator<Item = (String, Vec<u8>)>,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
+    let mut labeled_polygons: HashMap<String, MultiPolygon> = HashMap::new();
+    for (name, bytes) in items {
+        let geometry = wkb::wkb_to_geom(&mut bytes.as_slice())
+            .map_err(|e| format!("could not parse the WKB geometry of {name:?}: {e:?}"))?;
+        let polygons: Vec<Polygon> = match geometry {
+            geo::Geometry::Polygon(polygon) => vec![polygon],
+            geo::Geometry::MultiPolygon(multi_polygon) => multi_polygon.0,
+            _ => return Err(format!("the WKB geometry of {name:?} is not a polygon").into()),
+        };
+        labeled_polygons
+            .entry(name)
+            .or_insert(MultiPolygon::new(vec![]))
+            .0
+            .extend(polygons);
+    }
+    Ok(labeled_polygons)
 }
 
 /// Loads a HashMap from ISO_A2 country names to their borders from a GeoJSON file.
@@ -92,7 +815,9 @@ pub fn load_labeled_collection_polygons(path: &Path, label: &str) -> HashMap<Str
 /// # Arguments
 ///
 /// * `path` - The path to the GeoJSON file.
-pub fn load_countries(path: &Path) -> HashMap<String, MultiPolygon> {
+pub fn load_countries(
+    path: &Path,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
     load_labeled_collection_polygons(path, "ISO_A2")
 }
 
@@ -101,47 +826,339 @@ pub fn load_countries(path: &Path) -> HashMap<String, MultiPolygon> {
 /// # Arguments
 ///
 /// * `path` - The path to the GeoJSON file.
-pub fn load_provinces(path: &Path) -> HashMap<String, MultiPolygon> {
+pub fn load_provinces(
+    path: &Path,
+) -> Result<HashMap<String, MultiPolygon>, Box<dyn std::error::Error>> {
     load_labeled_collection_polygons(path, "iso_3166_2")
 }
 
+/// Returns a representative point for each labeled region, e.g. for placing labels on a map.
+///
+/// Unlike a centroid, each point is guaranteed to lie inside its region (or on its boundary for
+/// degenerate regions), even for concave regions or regions made of several parts. Regions
+/// without any geometry have no representative point and are omitted.
+///
+/// # Arguments
+///
+/// * `polygons` - The labeled polygons, e.g. as returned by `load_labeled_collection_polygons`.
+pub fn representative_points<T: Clone + Eq + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+) -> HashMap<T, Point> {
+    polygons
+        .iter()
+        .filter_map(|(label, polygon)| Some((label.clone(), polygon.interior_point()?)))
+        .collect()
+}
+
+/// A label whose polygons fall into several groups that are far apart, as reported by
+/// `find_dispersed_labels`.
+#[derive(Clone, Debug)]
+pub struct DispersedLabel<T> {
+    /// The dispersed label.
+    pub label: T,
+    /// The bounding box of each group of nearby polygons with the label.
+    pub groups: Vec<Rect>,
+}
+
+/// Finds labels whose polygons are implausibly far apart, which often indicates a data error such
+/// as a feature labeled with the wrong code.
+///
+/// A label's polygons are grouped so that polygons whose bounding boxes are within `max_gap`
+/// degrees of each other are in the same group. Labels with more than one group are reported,
+/// ordered by label. Some regions, like countries with overseas territories, are legitimately
+/// dispersed, so `max_gap` should be chosen with the dataset in mind.
+///
+/// # Arguments
+///
+/// * `polygons` - The labeled polygons, e.g. as returned by `load_labeled_collection_polygons`.
+/// * `max_gap` - The largest distance, in degrees, between polygons in the same group.
+pub fn find_dispersed_labels<T: Clone + Ord + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+    max_gap: f64,
+) -> Vec<DispersedLabel<T>> {
+    let mut dispersed: Vec<DispersedLabel<T>> = polygons
+        .iter()
+        .filter_map(|(label, polygon)| {
+            let bboxes: Vec<Rect> =
+                polygon.iter().filter_map(|part| part.bounding_rect()).collect();
+            let groups = group_nearby_rects(&bboxes, max_gap);
+            if groups.len() > 1 {
+                Some(DispersedLabel {
+                    label: label.clone(),
+                    groups,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    dispersed.sort_by(|a, b| a.label.cmp(&b.label));
+    dispersed
+}
+
+/// Groups rectangles that are within `max_gap` of each other, directly or through other
+/// rectangles, and returns the bounding box of each group.
+fn group_nearby_rects(rects: &[Rect], max_gap: f64) -> Vec<Rect> {
+    // union-find over the rectangles, joining every pair that is close enough
+    let mut parents: Vec<usize> = (0..rects.len()).collect();
+    fn root(parents: &mut Vec<usize>, mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for i in 0..rects.len() {
+        for j in i + 1..rects.len() {
+            if rect_gap(&rects[i], &rects[j]) <= max_gap {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a] = b;
+            }
+        }
+    }
+
+    let mut groups: Vec<(usize, Rect)> = vec![];
+    for i in 0..rects.len() {
+        let group = root(&mut parents, i);
+        match groups.iter_mut().find(|(root, _)| *root == group) {
+            Some((_, bbox)) => {
+                *bbox = Rect::new(
+                    (bbox.min().x.min(rects[i].min().x), bbox.min().y.min(rects[i].min().y)),
+                    (bbox.max().x.max(rects[i].max().x), bbox.max().y.max(rects[i].max().y)),
+                )
+            }
+            None => groups.push((group, rects[i])),
+        }
+    }
+    groups.into_iter().map(|(_, bbox)| bbox).collect()
+}
+
+/// Returns the distance between the closest points of two rectangles, or 0 if they intersect.
+fn rect_gap(a: &Rect, b: &Rect) -> f64 {
+    let dx = (a.min().x - b.max().x).max(b.min().x - a.max().x).max(0.0);
+    let dy = (a.min().y - b.max().y).max(b.min().y - a.max().y).max(0.0);
+    dx.hypot(dy)
+}
+
+/// Returns the total area of each labeled region in square degrees, e.g. for weighting query
+/// results by region size.
+///
+/// Square degrees shrink towards the poles; use `label_geodesic_areas` for areas in km².
+///
+/// # Arguments
+///
+/// * `polygons` - The labeled polygons, e.g. as returned by `load_labeled_collection_polygons`.
+pub fn label_areas<T: Clone + Eq + Hash>(polygons: &HashMap<T, MultiPolygon>) -> HashMap<T, f64> {
+    polygons
+        .iter()
+        .map(|(label, polygon)| (label.clone(), polygon.unsigned_area()))
+        .collect()
+}
+
+/// Returns the total area of each labeled region in square kilometers, measured on the WGS84
+/// ellipsoid.
+///
+/// # Arguments
+///
+/// * `polygons` - The labeled polygons, with coordinates in degrees of longitude and latitude.
+pub fn label_geodesic_areas<T: Clone + Eq + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+) -> HashMap<T, f64> {
+    polygons
+        .iter()
+        .map(|(label, polygon)| (label.clone(), polygon.geodesic_area_unsigned() / 1e6))
+        .collect()
+}
+
+
+/// Returns a 64-bit FNV-1a hash of the given bytes.
+///
+/// This is used to key tree caches on the contents of their source data; unlike `DefaultHasher`,
+/// its output is stable across Rust versions, so caches stay valid after toolchain upgrades.
+fn source_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
 
 /// Loads or computes a labeled partition tree from the given GeoJSON file and property label.
 /// If a cached version of the tree exists, it is loaded; otherwise, the tree is computed from scratch and saved.
 ///
+/// The cache is keyed on the label, the maximum depth, and a hash of the GeoJSON file's contents,
+/// so updating the source data automatically triggers a rebuild.
+///
 /// # Arguments
 ///
 /// * `cache_dir` - The directory where the tree cache will be stored.
 /// * `collection_path` - The path to the GeoJSON file.
 /// * `label` - The property to use as the label for the polygons.
 /// * `max_depth` - The maximum depth of the partition tree.
+///
+/// # Errors
+///
+/// Returns an error if the GeoJSON file cannot be loaded, if it contains no regions, or if the
+/// cache cannot be written. No cache is written for a dataset without regions.
 pub fn load_or_compute_label_tree(
     cache_dir: &Path,
     collection_path: &Path,
     label: &str,
     max_depth: usize,
-) -> LabeledPartitionTree<String> {
-    let cache_path = cache_dir.join(format!("{label}_label_tree_{max_depth}.json"));
-    let tree = match fs::read_to_string(&cache_path) {
-        Ok(string) => serde_json::from_str(&string).unwrap(),
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
+    load_or_compute_label_tree_with_options(
+        cache_dir,
+        collection_path,
+        label,
+        max_depth,
+        &LoadOptions::default(),
+    )
+}
+
+/// Loads or computes a labeled partition tree from the given GeoJSON file and property label,
+/// loading the polygons with the given options.
+/// If a cached version of the tree exists, it is loaded; otherwise, the tree is computed from scratch and saved.
+///
+/// Trees loaded with non-default options that change the loaded polygons are cached separately
+/// from trees loaded with the default options.
+///
+/// # Arguments
+///
+/// * `cache_dir` - The directory where the tree cache will be stored.
+/// * `collection_path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the polygons.
+/// * `max_depth` - The maximum depth of the partition tree.
+/// * `options` - Options controlling how the polygons are loaded.
+///
+/// # Errors
+///
+/// Returns an error if the GeoJSON file cannot be loaded, if it contains no regions, or if the
+/// cache cannot be written.
+pub fn load_or_compute_label_tree_with_options(
+    cache_dir: &Path,
+    collection_path: &Path,
+    label: &str,
+    max_depth: usize,
+    options: &LoadOptions,
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
+    // the source is read once, both to key the cache and to build the tree on a cache miss
+    let source = fs::read(collection_path)?;
+    let hash = source_hash(&source);
+    let mut cache_name = format!("{label}_label_tree_{max_depth}_{hash:016x}");
+    if let Some(distance) = options.buffer_degrees {
+        cache_name += &format!("_buffer_{distance}");
+    }
+    if options.strict_string_labels {
+        cache_name += "_strict";
+    }
+    if options.skip_values != LoadOptions::default().skip_values {
+        let skip_hash = source_hash(options.skip_values.join("\n").as_bytes());
+        cache_name += &format!("_skip_{skip_hash:016x}");
+    }
+    if let Some(decimals) = options.coordinate_decimals {
+        cache_name += &format!("_round_{decimals}");
+    }
+    if options.min_area > 0.0 {
+        cache_name += &format!("_min_area_{}", options.min_area);
+    }
+    if options.non_finite == NonFinitePolicy::Skip {
+        cache_name += "_skip_non_finite";
+    }
+    if options.normalize_label.is_some() {
+        cache_name += "_normalized";
+    }
+    if options.polygonize_lines {
+        cache_name += "_polygonized";
+    }
+    if options.coordinate_order == CoordOrder::LatLon {
+        cache_name += "_lat_lon";
+    }
+    let cache_path = cache_dir.join(format!("{cache_name}.json"));
+    let cached: Result<LabeledPartitionTree<String>, String> = fs::read_to_string(&cache_path)
+        .map_err(|e| e.to_string())
+        .and_then(|string| {
+            serde_json::from_str::<CachedTree<LabeledPartitionTree<String>>>(&string)
+                .map_err(|e| e.to_string())
+        })
+        .and_then(|cached| {
+            if cached.split == options.split {
+                Ok(cached.tree)
+            } else {
+                Err(format!(
+                    "The cached tree was split with {:?}, but {:?} was requested.",
+                    cached.split, options.split
+                ))
+            }
+        });
+    let tree = match cached {
+        Ok(tree) => tree,
         Err(e) => {
             println!("{e}");
             println!("Could not load saved {label} label tree; computing from scratch.");
-            let collection = load_labeled_collection_polygons(collection_path, label);
-            let tree = LabeledPartitionTree::from_labeled_polygons(
-                &collection.keys().cloned().collect(),
-                &collection,
-                Rect::new(Point::new(-180.0, 90.0), Point::new(180.0, -90.0)),
-                max_depth,
-                0,
-            );
-            let tree_json = serde_json::to_string(&tree).unwrap();
-            fs::write(cache_path, tree_json).unwrap();
+            let features = parse_feature_collection(collection_path, source)?;
+            let collection =
+                labeled_polygons_from_feature_collection_with_options(&features, label, options)?;
+            let (mut tree, timings) = TreeBuilder::new()
+                .max_depth(max_depth)
+                .branching(options.split)
+                .build_with_timings(&collection);
+            if options.verbose {
+                println!(
+                    "Built {label} label tree in {:?} ({:?} prefiltering, {:?} clipping); \
+                     nodes per depth: {:?}",
+                    timings.total, timings.prefilter, timings.clip, timings.nodes_per_depth
+                );
+            }
+            if let Some(decimals) = options.coordinate_decimals {
+                tree.round_coordinates(decimals);
+            }
+            // the tree is streamed to the file rather than serialized to a string first, which
+            // would double the peak memory use for large trees
+            let mut writer = BufWriter::new(fs::File::create(cache_path)?);
+            let cached = CachedTree {
+                split: options.split,
+                tree: &tree,
+            };
+            serde_json::to_writer(&mut writer, &cached)?;
+            writer.flush()?;
             tree
         }
     };
     println!("Loaded {label} label tree.");
-    tree
+    Ok(tree)
+}
+
+/// Loads or computes a labeled partition tree like `load_or_compute_label_tree`, but on tokio's
+/// blocking thread pool, so that building a tree doesn't stall an async runtime.
+///
+/// Several trees can be loaded or computed concurrently, e.g. with `tokio::join!`.
+///
+/// # Arguments
+///
+/// * `cache_dir` - The directory where the tree cache will be stored.
+/// * `collection_path` - The path to the GeoJSON file.
+/// * `label` - The property to use as the label for the polygons.
+/// * `max_depth` - The maximum depth of the partition tree.
+///
+/// # Errors
+///
+/// Returns an error if the GeoJSON file cannot be loaded, if it contains no regions, if the
+/// cache cannot be written, or if the blocking task panics.
+#[cfg(feature = "async")]
+pub async fn load_or_compute_label_tree_async(
+    cache_dir: &Path,
+    collection_path: &Path,
+    label: &str,
+    max_depth: usize,
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
+    let cache_dir = cache_dir.to_path_buf();
+    let collection_path = collection_path.to_path_buf();
+    let label = label.to_string();
+    // errors are converted to strings since boxed errors can't be sent between threads
+    let tree = tokio::task::spawn_blocking(move || {
+        load_or_compute_label_tree(&cache_dir, &collection_path, &label, max_depth)
+            .map_err(|e| e.to_string())
+    })
+    .await??;
+    Ok(tree)
 }
 
 /// Loads or computes a labeled country partition tree.
@@ -156,7 +1173,7 @@ pub fn load_or_compute_country_label_tree(
     cache_dir: &Path,
     countries_path: &Path,
     max_depth: usize,
-) -> LabeledPartitionTree<String> {
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
     load_or_compute_label_tree(cache_dir, countries_path, "ISO_A2", max_depth)
 }
 
@@ -173,6 +1190,157 @@ pub fn load_or_compute_province_label_tree(
     cache_dir: &Path,
     provinces_path: &Path,
     max_depth: usize,
-) -> LabeledPartitionTree<String> {
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
     load_or_compute_label_tree(cache_dir, provinces_path, "iso_3166_2", max_depth)
 }
+
+/// Loads or computes a labeled US county partition tree, labeled by the counties' five-digit FIPS
+/// codes.
+/// If a cached version of the tree exists, it is loaded; otherwise, the tree is computed from scratch and saved.
+///
+/// The counties are expected to be a GeoJSON conversion of the Census Bureau's TIGER/Line county
+/// shapefile, which stores the FIPS code in the `GEOID` property.
+///
+/// # Arguments
+///
+/// * `cache_dir` - The directory where the tree cache will be stored.
+/// * `counties_path` - The path to the GeoJSON file containing county data.
+/// * `max_depth` - The maximum depth of the partition tree.
+pub fn load_or_compute_county_label_tree(
+    cache_dir: &Path,
+    counties_path: &Path,
+    max_depth: usize,
+) -> Result<LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
+    load_or_compute_label_tree(cache_dir, counties_path, "GEOID", max_depth)
+}
+
+/// A dataset registered in a `DatasetRegistry`.
+struct RegisteredDataset {
+    path: PathBuf,
+    label: String,
+    max_depth: usize,
+    tree: OnceLock<LabeledPartitionTree<String>>,
+    properties: OnceLock<HashMap<String, JsonObject>>,
+}
+
+/// A registry of named datasets whose label trees are loaded or computed on first access.
+///
+/// This replaces a hardcoded `load_or_compute_*` call per dataset when an application uses
+/// several layers (e.g. countries, provinces, and counties).
+pub struct DatasetRegistry {
+    cache_dir: PathBuf,
+    datasets: HashMap<String, RegisteredDataset>,
+}
+
+impl DatasetRegistry {
+    /// Creates an empty registry whose tree caches are stored in `cache_dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache_dir` - The directory where the tree caches will be stored.
+    pub fn new(cache_dir: &Path) -> Self {
+        DatasetRegistry {
+            cache_dir: cache_dir.to_path_buf(),
+            datasets: HashMap::new(),
+        }
+    }
+
+    /// Registers a dataset under the given name, replacing any dataset already registered under it.
+    /// The dataset is not loaded until its tree is first requested.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to register the dataset under.
+    /// * `path` - The path to the GeoJSON file.
+    /// * `label` - The property to use as the label for the polygons.
+    /// * `max_depth` - The maximum depth of the partition tree.
+    pub fn register(
+        &mut self,
+        name: &str,
+        path: &Path,
+        label: &str,
+        max_depth: usize,
+    ) -> &mut Self {
+        self.datasets.insert(
+            name.to_string(),
+            RegisteredDataset {
+                path: path.to_path_buf(),
+                label: label.to_string(),
+                max_depth,
+                tree: OnceLock::new(),
+                properties: OnceLock::new(),
+            },
+        );
+        self
+    }
+
+    /// Returns whether a dataset is registered under the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.datasets.contains_key(name)
+    }
+
+    /// Returns the sorted names of the registered datasets.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.datasets.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the label tree of the named dataset, loading or computing it on first access.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no dataset is registered under `name` or if its tree cannot be loaded
+    /// or computed.
+    pub fn tree(
+        &self,
+        name: &str,
+    ) -> Result<&LabeledPartitionTree<String>, Box<dyn std::error::Error>> {
+        let dataset = self
+            .datasets
+            .get(name)
+            .ok_or_else(|| format!("no dataset is registered as {name:?}"))?;
+        if let Some(tree) = dataset.tree.get() {
+            return Ok(tree);
+        }
+
+        let tree = load_or_compute_label_tree(
+            &self.cache_dir,
+            &dataset.path,
+            &dataset.label,
+            dataset.max_depth,
+        )?;
+        Ok(dataset.tree.get_or_init(|| tree))
+    }
+
+    /// Returns the feature properties of each label of the named dataset, loading them on first
+    /// access; see `load_label_properties`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no dataset is registered under `name` or if its GeoJSON file cannot be
+    /// loaded.
+    pub fn properties(
+        &self,
+        name: &str,
+    ) -> Result<&HashMap<String, JsonObject>, Box<dyn std::error::Error>> {
+        let dataset = self
+            .datasets
+            .get(name)
+            .ok_or_else(|| format!("no dataset is registered as {name:?}"))?;
+        if let Some(properties) = dataset.properties.get() {
+            return Ok(properties);
+        }
+
+        let properties = load_label_properties(&dataset.path, &dataset.label)?;
+        Ok(dataset.properties.get_or_init(|| properties))
+    }
+}
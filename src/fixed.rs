@@ -0,0 +1,340 @@
+//! Labeled partition trees over fixed-point integer coordinates, for targets without fast floating
+//! point.
+
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use geo::{LineString, MultiPolygon, Polygon, Rect};
+
+use crate::labeling::LabeledPartitionTree;
+
+/// The number of fixed-point units per degree, which gives a resolution of about 11 cm.
+pub const UNITS_PER_DEGREE: f64 = 1e6;
+
+/// A coordinate in fixed-point units, i.e. longitude and latitude in millionths of a degree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct FixedCoord {
+    /// The longitude in millionths of a degree.
+    pub x: i32,
+    /// The latitude in millionths of a degree.
+    pub y: i32,
+}
+
+impl FixedCoord {
+    /// Converts a longitude and latitude in degrees to the nearest fixed-point coordinate.
+    pub fn from_degrees(lon: f64, lat: f64) -> Self {
+        FixedCoord {
+            x: (lon * UNITS_PER_DEGREE).round() as i32,
+            y: (lat * UNITS_PER_DEGREE).round() as i32,
+        }
+    }
+
+    /// Converts the coordinate back to a longitude and latitude in degrees.
+    pub fn to_degrees(&self) -> (f64, f64) {
+        (
+            self.x as f64 / UNITS_PER_DEGREE,
+            self.y as f64 / UNITS_PER_DEGREE,
+        )
+    }
+}
+
+/// An axis-aligned rectangle in fixed-point coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct FixedRect {
+    min: FixedCoord,
+    max: FixedCoord,
+}
+
+impl FixedRect {
+    fn from_rect(rect: Rect) -> Self {
+        FixedRect {
+            min: FixedCoord::from_degrees(rect.min().x, rect.min().y),
+            max: FixedCoord::from_degrees(rect.max().x, rect.max().y),
+        }
+    }
+
+    /// Returns whether the point lies strictly inside the rectangle. Like `Rect::contains`, this
+    /// excludes the boundary, so fixed-point and floating point trees agree on points that lie on
+    /// cell boundaries, which neither labels.
+    fn contains(&self, point: FixedCoord) -> bool {
+        self.min.x < point.x && point.x < self.max.x && self.min.y < point.y && point.y < self.max.y
+    }
+
+    /// Returns whether the rectangles intersect, including if they only touch.
+    fn intersects(&self, other: &FixedRect) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    /// Splits the rectangle into its four quadrants.
+    fn quadrants(&self) -> [FixedRect; 4] {
+        // the midpoint is computed in i64 so that the sum of the coordinates can't overflow
+        let mid = FixedCoord {
+            x: ((self.min.x as i64 + self.max.x as i64) / 2) as i32,
+            y: ((self.min.y as i64 + self.max.y as i64) / 2) as i32,
+        };
+        let rect = |min: (i32, i32), max: (i32, i32)| FixedRect {
+            min: FixedCoord { x: min.0, y: min.1 },
+            max: FixedCoord { x: max.0, y: max.1 },
+        };
+        [
+            rect((self.min.x, self.min.y), (mid.x, mid.y)),
+            rect((self.min.x, mid.y), (mid.x, self.max.y)),
+            rect((mid.x, self.min.y), (self.max.x, mid.y)),
+            rect((mid.x, mid.y), (self.max.x, self.max.y)),
+        ]
+    }
+}
+
+/// A polygon in fixed-point coordinates.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct FixedPolygon {
+    bbox: FixedRect,
+    exterior: Vec<FixedCoord>,
+    interiors: Vec<Vec<FixedCoord>>,
+}
+
+impl FixedPolygon {
+    /// Converts a polygon to fixed-point coordinates.
+    fn from_polygon(polygon: &Polygon) -> Self {
+        let exterior = fixed_ring(polygon.exterior());
+        FixedPolygon {
+            bbox: ring_bbox(&exterior),
+            exterior,
+            interiors: polygon.interiors().iter().map(fixed_ring).collect(),
+        }
+    }
+
+    /// Returns whether the polygon contains the point, using only integer arithmetic. Points on
+    /// the boundary may or may not be contained.
+    fn contains(&self, point: FixedCoord) -> bool {
+        self.bbox.contains(point)
+            && ring_contains(&self.exterior, point)
+            && !self.interiors.iter().any(|ring| ring_contains(ring, point))
+    }
+}
+
+/// Returns whether a closed ring contains the point by counting the ring's crossings of a ray
+/// cast from the point in the positive x direction.
+fn ring_contains(ring: &[FixedCoord], point: FixedCoord) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let (a, b) = (edge[0], edge[1]);
+        if (a.y > point.y) != (b.y > point.y) {
+            // the products of coordinate differences fit comfortably in an i64
+            let lhs = (point.x as i64 - a.x as i64) * (b.y as i64 - a.y as i64);
+            let rhs = (point.y as i64 - a.y as i64) * (b.x as i64 - a.x as i64);
+            let crosses = if b.y > a.y { lhs < rhs } else { lhs > rhs };
+            if crosses {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Converts a ring to fixed-point coordinates.
+fn fixed_ring(ring: &LineString) -> Vec<FixedCoord> {
+    ring.0
+        .iter()
+        .map(|coord| FixedCoord::from_degrees(coord.x, coord.y))
+        .collect()
+}
+
+/// A `LabeledPartitionTree` whose cells and polygons are stored in fixed-point coordinates, so that
+/// queries use only integer arithmetic, e.g. for embedded devices where `f64` is slow. Results are
+/// deterministic across platforms at the cost of rounding coordinates to `UNITS_PER_DEGREE`.
+///
+/// Trees can be built directly with `from_labeled_polygons`, which converts the polygons to
+/// fixed-point coordinates up front and then uses only integer arithmetic. Polygons aren't
+/// clipped to cells, which would need floating point intersections, so leaves share the whole
+/// polygon parts overlapping them and queries in leaves with large parts are slower. Trees can
+/// also be converted from a floating point tree with `from_tree`, keeping its structure and
+/// clipped polygons, e.g. when the tree is built ahead of time on a more capable machine.
+///
+/// Like floating point trees, cells exclude their boundaries, so points exactly on a cell
+/// boundary are unlabeled.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct FixedLabeledPartitionTree<T: Eq + Hash> {
+    children: Vec<FixedLabeledPartitionTree<T>>,
+    polygons: HashMap<T, Vec<Arc<FixedPolygon>>>,
+    bbox: FixedRect,
+    /// Whether the cells of the node's children may overlap, e.g. at the root of a merged tree.
+    #[serde(default)]
+    overlapping: bool,
+}
+
+impl<T: Clone + Eq + Hash> FixedLabeledPartitionTree<T> {
+    /// Builds a fixed-point tree over the whole world from labeled polygons, splitting cells into
+    /// quadrants with integer arithmetic.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `max_depth` - The maximum depth of the tree.
+    pub fn from_labeled_polygons(polygons: &HashMap<T, MultiPolygon>, max_depth: usize) -> Self {
+        let parts: Vec<(T, Arc<FixedPolygon>)> = polygons
+            .iter()
+            .flat_map(|(label, multi_polygon)| {
+                multi_polygon.iter().map(move |polygon| {
+                    (label.clone(), Arc::new(FixedPolygon::from_polygon(polygon)))
+                })
+            })
+            .collect();
+        let root = FixedRect {
+            min: FixedCoord::from_degrees(-180.0, -90.0),
+            max: FixedCoord::from_degrees(180.0, 90.0),
+        };
+        let selected: Vec<&(T, Arc<FixedPolygon>)> = parts.iter().collect();
+        FixedLabeledPartitionTree::build_node(root, &selected, 0, max_depth)
+    }
+
+    /// Recursively builds the node covering `bbox` from the polygon parts overlapping it.
+    fn build_node(
+        bbox: FixedRect,
+        parts: &[&(T, Arc<FixedPolygon>)],
+        depth: usize,
+        max_depth: usize,
+    ) -> Self {
+        if depth >= max_depth || parts.is_empty() {
+            let mut polygons: HashMap<T, Vec<Arc<FixedPolygon>>> = HashMap::new();
+            for (label, part) in parts {
+                polygons
+                    .entry(label.clone())
+                    .or_default()
+                    .push(Arc::clone(part));
+            }
+            return FixedLabeledPartitionTree {
+                children: vec![],
+                polygons,
+                bbox,
+                overlapping: false,
+            };
+        }
+
+        let children = bbox
+            .quadrants()
+            .iter()
+            .map(|cell| {
+                let parts: Vec<&(T, Arc<FixedPolygon>)> = parts
+                    .iter()
+                    .copied()
+                    .filter(|(_, part)| part.bbox.intersects(cell))
+                    .collect();
+                FixedLabeledPartitionTree::build_node(*cell, &parts, depth + 1, max_depth)
+            })
+            .collect();
+        FixedLabeledPartitionTree {
+            children,
+            polygons: HashMap::new(),
+            bbox,
+            overlapping: false,
+        }
+    }
+
+    /// Converts a floating point tree into a fixed-point tree with the same structure.
+    ///
+    /// # Arguments
+    /// * `tree` - The tree to convert.
+    pub fn from_tree(tree: &LabeledPartitionTree<T>) -> Self {
+        FixedLabeledPartitionTree {
+            children: tree
+                .children
+                .iter()
+                .map(FixedLabeledPartitionTree::from_tree)
+                .collect(),
+            polygons: tree
+                .polygons
+                .iter()
+                .map(|(label, multi_polygon)| {
+                    let polygons = multi_polygon
+                        .iter()
+                        .map(|polygon| Arc::new(FixedPolygon::from_polygon(polygon)))
+                        .collect();
+                    (label.clone(), polygons)
+                })
+                .collect(),
+            bbox: FixedRect::from_rect(tree.bbox),
+            overlapping: tree.overlapping,
+        }
+    }
+
+    /// Returns the label of the partition that contains the given point.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check, in fixed-point coordinates.
+    pub fn label(&self, point: FixedCoord) -> Option<T> {
+        self.label_ref(point).cloned()
+    }
+
+    /// Returns a reference to the label of the partition that contains the given point.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check, in fixed-point coordinates.
+    pub fn label_ref(&self, point: FixedCoord) -> Option<&T> {
+        // same single-path descent as `LabeledPartitionTree::label_ref`
+        let mut node = self;
+        while !node.children.is_empty() {
+            if node.overlapping {
+                return node.label_ref_backtracking(point);
+            }
+            node = node
+                .children
+                .iter()
+                .find(|child| child.bbox.contains(point))?;
+        }
+        node.leaf_label(point)
+    }
+
+    /// Returns the label of the partition that contains the given point, searching every child
+    /// that contains it in order until one labels it, e.g. below a node whose children overlap.
+    fn label_ref_backtracking(&self, point: FixedCoord) -> Option<&T> {
+        // children are pushed in reverse so they are visited in order
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.children.is_empty() {
+                if let Some(label) = node.leaf_label(point) {
+                    return Some(label);
+                }
+            } else {
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|child| child.bbox.contains(point)),
+                );
+            }
+        }
+        None
+    }
+
+    /// Returns the label of the polygon in this leaf that contains the given point, if any.
+    fn leaf_label(&self, point: FixedCoord) -> Option<&T> {
+        self.polygons
+            .iter()
+            .find(|(_, polygons)| polygons.iter().any(|polygon| polygon.contains(point)))
+            .map(|(label, _)| label)
+    }
+}
+
+/// Returns the bounding box of a ring, or an empty box at the origin for empty rings.
+fn ring_bbox(ring: &[FixedCoord]) -> FixedRect {
+    let origin = FixedCoord { x: 0, y: 0 };
+    let first = ring.first().copied().unwrap_or(origin);
+    ring.iter().fold(
+        FixedRect {
+            min: first,
+            max: first,
+        },
+        |bbox, coord| FixedRect {
+            min: FixedCoord {
+                x: bbox.min.x.min(coord.x),
+                y: bbox.min.y.min(coord.y),
+            },
+            max: FixedCoord {
+                x: bbox.max.x.max(coord.x),
+                y: bbox.max.y.max(coord.y),
+            },
+        },
+    )
+}
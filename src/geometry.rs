@@ -0,0 +1,162 @@
+//! Geometry helpers used when preparing labeled regions for a `LabeledPartitionTree`.
+
+use geo::{
+    Area, BooleanOps, Contains, InteriorPoint, LineString, MultiLineString, MultiPolygon, Polygon,
+};
+use geo_types::Coord;
+
+/// Applies `f` to every coordinate of every ring in a multipolygon.
+///
+/// # Arguments
+///
+/// * `multi_polygon` - The multipolygon to modify in place.
+/// * `f` - The function to apply to each coordinate.
+pub fn map_coords_mut(multi_polygon: &mut MultiPolygon, mut f: impl FnMut(&mut Coord)) {
+    multi_polygon.iter_mut().for_each(|polygon| {
+        polygon.exterior_mut(|exterior| exterior.0.iter_mut().for_each(&mut f));
+        polygon.interiors_mut(|interiors| {
+            interiors
+                .iter_mut()
+                .for_each(|interior| interior.0.iter_mut().for_each(&mut f))
+        });
+    });
+}
+
+/// Approximately dilates a multipolygon by the given distance in degrees.
+///
+/// geo 0.23 has no buffer operation, so this is NOT a true buffer: it is the union of the region
+/// with eight copies of itself, shifted by `distance` in the eight compass directions. The result
+/// always contains the original region and never reaches farther than `distance` from it, but it
+/// falls short of a true buffer in several ways:
+///
+/// * Around corners, points within `distance` of the region in directions between the compass
+///   directions are missed, so dilated corners are notched instead of rounded.
+/// * Parts of the region narrower than `distance` (thin spits, or the walls around narrow gaps
+///   and concave notches) do not overlap their shifted copies, leaving uncovered slivers between
+///   the copies that a true buffer would fill.
+/// * Each copy is unioned into the accumulated result in turn, so the cost is that of eight
+///   successive boolean unions over a growing polygon, i.e. several times the cost of a single
+///   union of the input with itself.
+///
+/// # Arguments
+///
+/// * `multi_polygon` - The multipolygon to dilate.
+/// * `distance` - The buffer distance in degrees. Non-positive distances return the input unchanged.
+pub fn buffer_multi_polygon(multi_polygon: &MultiPolygon, distance: f64) -> MultiPolygon {
+    if distance <= 0.0 {
+        return multi_polygon.clone();
+    }
+
+    let diagonal = distance / std::f64::consts::SQRT_2;
+    let offsets = [
+        (distance, 0.0),
+        (-distance, 0.0),
+        (0.0, distance),
+        (0.0, -distance),
+        (diagonal, diagonal),
+        (diagonal, -diagonal),
+        (-diagonal, diagonal),
+        (-diagonal, -diagonal),
+    ];
+    offsets
+        .iter()
+        .fold(multi_polygon.clone(), |buffered, (dx, dy)| {
+            let mut shifted = multi_polygon.clone();
+            map_coords_mut(&mut shifted, |coord| {
+                coord.x += dx;
+                coord.y += dy;
+            });
+            buffered.union(&shifted)
+        })
+}
+
+/// Assembles closed boundary linework into polygons, e.g. for datasets that only provide
+/// administrative boundaries as line strings.
+///
+/// geo 0.23 has no polygonize operation, so line strings are stitched together wherever their
+/// endpoints coincide exactly until they close into rings. Rings nested inside an odd number of
+/// other rings become holes of the smallest ring containing them. Returns the polygons along with
+/// the linework that could not be closed into rings.
+///
+/// # Arguments
+///
+/// * `lines` - The boundary linework to polygonize.
+pub fn polygonize(lines: &MultiLineString) -> (MultiPolygon, Vec<LineString>) {
+    let mut rings: Vec<LineString> = vec![];
+    let mut unclosed: Vec<LineString> = vec![];
+    let mut pieces: Vec<Vec<Coord>> = lines
+        .iter()
+        .filter(|line| !line.0.is_empty())
+        .map(|line| line.0.clone())
+        .collect();
+
+    while let Some(mut current) = pieces.pop() {
+        while !is_closed(&current) {
+            let end = current[current.len() - 1];
+            let next = pieces
+                .iter()
+                .position(|piece| piece[0] == end || piece[piece.len() - 1] == end);
+            match next {
+                Some(index) => {
+                    let mut piece = pieces.swap_remove(index);
+                    if piece[0] != end {
+                        piece.reverse();
+                    }
+                    current.extend(piece.into_iter().skip(1));
+                }
+                None => break,
+            }
+        }
+        if is_closed(&current) {
+            rings.push(LineString::new(current));
+        } else {
+            unclosed.push(LineString::new(current));
+        }
+    }
+
+    let shells: Vec<Polygon> = rings
+        .iter()
+        .map(|ring| Polygon::new(ring.clone(), vec![]))
+        .collect();
+    // the rings containing each ring, found by testing an interior point of the ring's polygon
+    let containers: Vec<Vec<usize>> = shells
+        .iter()
+        .enumerate()
+        .map(|(i, shell)| match shell.interior_point() {
+            Some(point) => (0..shells.len())
+                .filter(|&j| j != i && shells[j].contains(&point))
+                .collect(),
+            None => vec![],
+        })
+        .collect();
+
+    let mut polygons: Vec<Polygon> = vec![];
+    let mut polygon_indices: Vec<Option<usize>> = vec![None; rings.len()];
+    for (i, ring) in rings.iter().enumerate() {
+        if containers[i].len() % 2 == 0 {
+            polygon_indices[i] = Some(polygons.len());
+            polygons.push(Polygon::new(ring.clone(), vec![]));
+        }
+    }
+    for (i, ring) in rings.iter().enumerate() {
+        if containers[i].len() % 2 == 1 {
+            let parent = containers[i]
+                .iter()
+                .filter(|&&j| polygon_indices[j].is_some())
+                .min_by(|&&a, &&b| {
+                    shells[a]
+                        .unsigned_area()
+                        .total_cmp(&shells[b].unsigned_area())
+                });
+            if let Some(index) = parent.and_then(|&j| polygon_indices[j]) {
+                polygons[index].interiors_push(ring.clone());
+            }
+        }
+    }
+    (MultiPolygon::new(polygons), unclosed)
+}
+
+/// Returns whether a piece of linework forms a closed ring.
+fn is_closed(coords: &[Coord]) -> bool {
+    coords.len() >= 4 && coords[0] == coords[coords.len() - 1]
+}
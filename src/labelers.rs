@@ -0,0 +1,171 @@
+//! Labelers that wrap or combine `LabeledPartitionTree`s to change how queries are answered.
+
+use std::{collections::HashMap, hash::Hash, sync::Mutex};
+
+use geo::Point;
+
+use crate::labeling::{LabeledPartitionTree, QueryPoint};
+#[cfg(feature = "shared")]
+use crate::shared::SharedTree;
+
+/// Something that can label points, e.g. a `LabeledPartitionTree` or one of the wrappers in this
+/// module. This lets labelers be composed, e.g. with `ChainLabeler`.
+pub trait Labeler<T> {
+    /// Returns the label of the region that contains the given point, or `None` if there is none.
+    fn label(&self, point: &Point) -> Option<T>;
+}
+
+impl<T: Clone + Eq + Hash> Labeler<T> for LabeledPartitionTree<T> {
+    fn label(&self, point: &Point) -> Option<T> {
+        LabeledPartitionTree::label(self, point)
+    }
+}
+
+#[cfg(feature = "shared")]
+impl<T: Clone + Eq + Hash> Labeler<T> for SharedTree<T> {
+    fn label(&self, point: &Point) -> Option<T> {
+        SharedTree::label(self, point)
+    }
+}
+
+impl<T: Clone + Eq + Hash> Labeler<T> for CachingLabeler<T> {
+    fn label(&self, point: &Point) -> Option<T> {
+        CachingLabeler::label(self, point)
+    }
+}
+
+/// A labeler that queries several labelers in order and returns the first label found, e.g. to
+/// combine a detailed tree of a focus region with a coarse global tree as a fallback.
+pub struct ChainLabeler<T> {
+    labelers: Vec<Box<dyn Labeler<T> + Send + Sync>>,
+}
+
+impl<T> ChainLabeler<T> {
+    /// Creates a chain with no labelers, which labels nothing.
+    pub fn new() -> Self {
+        ChainLabeler { labelers: vec![] }
+    }
+
+    /// Adds a labeler to the end of the chain, to be queried if all of the labelers before it
+    /// return `None`.
+    pub fn then(mut self, labeler: impl Labeler<T> + Send + Sync + 'static) -> Self {
+        self.labelers.push(Box::new(labeler));
+        self
+    }
+}
+
+impl<T> Default for ChainLabeler<T> {
+    fn default() -> Self {
+        ChainLabeler::new()
+    }
+}
+
+impl<T> Labeler<T> for ChainLabeler<T> {
+    fn label(&self, point: &Point) -> Option<T> {
+        self.labelers
+            .iter()
+            .find_map(|labeler| labeler.label(point))
+    }
+}
+
+/// A `LabeledPartitionTree` wrapper that memoizes the labels of recently queried grid cells, for
+/// query streams where the same locations come up over and over.
+///
+/// Query points are grouped into the squares of a grid with the given resolution, and the label
+/// of the first point queried in each square is cached for the whole square. Coarser grids get
+/// more cache hits, but points near region borders may get the label of a neighboring region. The
+/// cache is safe to share between threads.
+///
+/// The cache keeps two generations of entries: once the current generation holds half of the
+/// capacity, it replaces the previous generation and a new one is started. Entries found in the
+/// previous generation are moved to the current one, so recently used cells stay cached while old
+/// ones are evicted, much like an LRU cache.
+pub struct CachingLabeler<T: Eq + Hash> {
+    tree: LabeledPartitionTree<T>,
+    resolution: f64,
+    capacity: usize,
+    cache: Mutex<CacheGenerations<T>>,
+}
+
+/// The current and previous generations of a `CachingLabeler`'s cache.
+struct CacheGenerations<T> {
+    current: HashMap<(i64, i64), Option<T>>,
+    previous: HashMap<(i64, i64), Option<T>>,
+}
+
+impl<T: Clone + Eq + Hash> CachingLabeler<T> {
+    /// Wraps a tree in a cache.
+    ///
+    /// # Arguments
+    /// * `tree` - The tree used to answer queries that miss the cache.
+    /// * `resolution` - The spacing of the grid that query points are snapped to, in degrees.
+    /// * `capacity` - The maximum number of grid cells to cache.
+    pub fn new(tree: LabeledPartitionTree<T>, resolution: f64, capacity: usize) -> Self {
+        CachingLabeler {
+            tree,
+            resolution,
+            capacity,
+            cache: Mutex::new(CacheGenerations {
+                current: HashMap::new(),
+                previous: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the wrapped tree.
+    pub fn tree(&self) -> &LabeledPartitionTree<T> {
+        &self.tree
+    }
+
+    /// Returns the label of the partition that contains the given point, or the cached label of
+    /// its grid square if one was already queried.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn label(&self, point: impl QueryPoint) -> Option<T> {
+        let point = point.to_point();
+        let key = (
+            (point.x() / self.resolution).floor() as i64,
+            (point.y() / self.resolution).floor() as i64,
+        );
+
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(label) = cache.current.get(&key) {
+                return label.clone();
+            }
+            if let Some(label) = cache.previous.remove(&key) {
+                cache.insert(key, label.clone(), self.capacity);
+                return label;
+            }
+        }
+
+        // the lock isn't held while querying the tree, so concurrent misses don't wait on each
+        // other; at worst, two threads label the same cell. The query point itself is labeled
+        // rather than a grid point, since grid points can lie on cell boundaries (e.g. along the
+        // equator), which no cell contains
+        let label = self.tree.label(&point);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, label.clone(), self.capacity);
+        label
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.current.clear();
+        cache.previous.clear();
+    }
+}
+
+impl<T> CacheGenerations<T> {
+    /// Inserts an entry into the current generation, starting a new generation if it is full.
+    fn insert(&mut self, key: (i64, i64), label: Option<T>, capacity: usize) {
+        if self.current.len() >= (capacity / 2).max(1) {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(key, label);
+    }
+}
@@ -1,135 +1,2526 @@
-use geo::{BooleanOps, Contains, CoordsIter, Intersects, MultiPolygon, Point, Rect};
+use geo::{
+    Area, BooleanOps, BoundingRect, Contains, CoordsIter, EuclideanDistance, HaversineDistance,
+    Intersects, MultiPolygon, Point, Polygon, Rect,
+};
+use geo_types::Coord;
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::map_coords_mut;
+#[cfg(feature = "plotting")]
 use plotters::{
-    prelude::{BitMapBackend, ChartBuilder, IntoDrawingArea},
+    coord::Shift,
+    prelude::{BitMapBackend, ChartBuilder, DrawingArea, DrawingBackend, IntoDrawingArea},
     series::LineSeries,
-    style::{BLACK, RED, WHITE},
+    style::{Palette, Palette99, BLACK, RED, WHITE},
 };
-use std::{collections::HashMap, hash::Hash, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    mem::size_of,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "plotting")]
+use std::{ops::Range, path::Path};
+#[cfg(feature = "h3")]
+use h3o::{CellIndex, LatLng};
+
+
+/// The metric used to measure the distance between a point and a region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DistanceMetric {
+    /// Planar distance in degrees of longitude/latitude.
+    Euclidean,
+    /// Great-circle distance in kilometers.
+    ///
+    /// The closest point on each edge of the region is found in a plane whose longitudes are
+    /// scaled by the cosine of the query point's latitude, and the smallest great-circle distance
+    /// to those points is reported, which is accurate for the short distances nearest-region
+    /// queries care about, even at high latitudes.
+    Haversine,
+}
+
+impl DistanceMetric {
+    /// Returns the distance from `point` to `polygon` under this metric.
+    /// Points inside the polygon are at distance 0.
+    ///
+    /// # Arguments
+    /// * `point` - The point to measure from.
+    /// * `polygon` - The region to measure to.
+    pub fn distance(&self, point: &Point, polygon: &MultiPolygon) -> f64 {
+        match self {
+            DistanceMetric::Euclidean => point.euclidean_distance(polygon),
+            DistanceMetric::Haversine => {
+                if polygon.contains(point) {
+                    return 0.0;
+                }
+                polygon
+                    .iter()
+                    .flat_map(|polygon| {
+                        std::iter::once(polygon.exterior()).chain(polygon.interiors())
+                    })
+                    .flat_map(|ring| ring.lines())
+                    .map(|line| haversine_to_segment(point, line.start, line.end))
+                    .fold(f64::INFINITY, f64::min)
+            }
+        }
+    }
+}
+
+/// Returns the great-circle distance in kilometers from `point` to the segment from `start` to
+/// `end`.
+///
+/// Degrees of longitude shrink towards the poles, so the closest point on the segment is found
+/// after scaling longitudes by the cosine of the point's latitude, which keeps distances around
+/// the point roughly proportional to great-circle distances.
+fn haversine_to_segment(point: &Point, start: Coord, end: Coord) -> f64 {
+    let scale = point.y().to_radians().cos();
+    let (dx, dy) = ((end.x - start.x) * scale, end.y - start.y);
+    let (px, py) = ((point.x() - start.x) * scale, point.y() - start.y);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        ((px * dx + py * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let closest = Point::new(
+        start.x + t * (end.x - start.x),
+        start.y + t * (end.y - start.y),
+    );
+    point.haversine_distance(&closest) / 1000.0
+}
+
+/// Returns a rectangle containing every point within `distance` of `point` under `metric`, e.g.
+/// to find the leaves that nearest-region queries need to search.
+fn search_window(point: &Point, distance: f64, metric: DistanceMetric) -> Rect {
+    match metric {
+        DistanceMetric::Euclidean => Rect::new(
+            (point.x() - distance, point.y() - distance),
+            (point.x() + distance, point.y() + distance),
+        ),
+        DistanceMetric::Haversine => {
+            // one degree of latitude is about 111 km; degrees of longitude shrink towards the
+            // poles, so the longitude radius is taken at the window's latitude closest to a pole
+            let lat_radius = distance / 111.0;
+            let min_lat = (point.y() - lat_radius).max(-90.0);
+            let max_lat = (point.y() + lat_radius).min(90.0);
+            let polemost_lat = min_lat.abs().max(max_lat.abs());
+            let lon_radius = lat_radius / polemost_lat.to_radians().cos();
+            let (min_lon, max_lon) = (point.x() - lon_radius, point.x() + lon_radius);
+            // circles around the poles or crossing the antimeridian cover every longitude
+            let (min_lon, max_lon) = if min_lon >= -180.0 && max_lon <= 180.0 {
+                (min_lon, max_lon)
+            } else {
+                (-180.0, 180.0)
+            };
+            Rect::new((min_lon, min_lat), (max_lon, max_lat))
+        }
+    }
+}
+
+/// The rule used to split a cell's bounding box into child cells while building a tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SplitStrategy {
+    /// Always split cells into four quadrants.
+    #[default]
+    Quadrants,
+    /// Bisect only the longer axis of cells that are at least twice as long as they are tall (or
+    /// vice versa), and split the rest into quadrants. This keeps cells close to square; e.g. the
+    /// 360x180 world is first split into two 180x180 halves.
+    LongestAxis,
+    /// Like `LongestAxis`, but measures the width of cells in degrees of longitude scaled by the
+    /// cosine of their central latitude, i.e. in proportion to their actual east-west extent.
+    /// Degrees of longitude converge towards the poles, so high-latitude cells are split along
+    /// their latitude more often than along their longitude, keeping cells closer to equal area.
+    LatitudeAware,
+}
+
+impl SplitStrategy {
+    /// Splits a bounding box into child bounding boxes according to this strategy.
+    ///
+    /// # Arguments
+    /// * `bbox` - The bounding box to split.
+    pub fn split(&self, bbox: Rect) -> Vec<Rect> {
+        match self {
+            SplitStrategy::Quadrants => quadrants(bbox),
+            SplitStrategy::LongestAxis => split_longest_axis(bbox, bbox.width()),
+            SplitStrategy::LatitudeAware => {
+                let width = bbox.width() * bbox.center().y.to_radians().cos().abs();
+                split_longest_axis(bbox, width)
+            }
+        }
+    }
+}
+
+/// Bisects the longer axis of a bounding box that is at least twice as long as it is tall (or
+/// vice versa) and splits other bounding boxes into quadrants, measuring the box's width as
+/// `width`.
+fn split_longest_axis(bbox: Rect, width: f64) -> Vec<Rect> {
+    if width >= 2.0 * bbox.height() {
+        bbox.split_x().to_vec()
+    } else if bbox.height() >= 2.0 * width {
+        bbox.split_y().to_vec()
+    } else {
+        quadrants(bbox)
+    }
+}
+
+/// Splits a bounding box into its four quadrants.
+fn quadrants(bbox: Rect) -> Vec<Rect> {
+    let [ab, cd] = bbox.split_x();
+    let [a, b] = ab.split_y();
+    let [c, d] = cd.split_y();
+    vec![a, b, c, d]
+}
+
+/// A builder for `LabeledPartitionTree`s.
+///
+/// By default, trees cover the whole world, have a maximum depth of 6, and split cells into
+/// quadrants.
+#[derive(Clone, Debug)]
+pub struct TreeBuilder {
+    max_depth: usize,
+    root: Rect,
+    split: SplitStrategy,
+    mask: Option<MultiPolygon>,
+    clip: bool,
+    overload_threshold: Option<usize>,
+}
+
+impl Default for TreeBuilder {
+    fn default() -> Self {
+        TreeBuilder {
+            max_depth: 6,
+            root: Rect::new(Point::new(-180.0, 90.0), Point::new(180.0, -90.0)),
+            split: SplitStrategy::default(),
+            mask: None,
+            clip: true,
+            overload_threshold: None,
+        }
+    }
+}
+
+impl TreeBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        TreeBuilder::default()
+    }
+
+    /// Sets the maximum depth of the tree. Deeper trees tend to result in faster queries, but take
+    /// much longer to construct.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the bounding box covered by the root of the tree.
+    pub fn root(mut self, bbox: Rect) -> Self {
+        self.root = bbox;
+        self
+    }
+
+    /// Sets the rule used to split cells into child cells.
+    pub fn branching(mut self, split: SplitStrategy) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Sets a mask outside of which no queries need to be answered, e.g. a land mask for a
+    /// country tree. Cells that do not intersect the mask are not subdivided and label nothing,
+    /// which can shrink trees with lots of empty space considerably.
+    ///
+    /// The mask should cover every labeled region; parts of regions outside it may be dropped.
+    pub fn mask(mut self, mask: MultiPolygon) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Sets whether polygons in leaves at the maximum depth are clipped to their cells (the
+    /// default).
+    ///
+    /// Clipping is the main cost of building a tree. Without it, builds are much faster, but each
+    /// leaf references every whole polygon it overlaps, so queries in those leaves are slower.
+    /// The leaves share the original polygons in memory, but serialized trees store a copy of
+    /// each polygon per leaf. Labels are the same either way, since queries only check a leaf's
+    /// polygons for points inside the leaf's cell.
+    pub fn clip(mut self, clip: bool) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Prints a warning after each build if any leaves hold more than `threshold` polygons.
+    ///
+    /// Queries in crowded leaves are slow, so many overloaded leaves suggest that the tree should
+    /// be deeper. See also `LabeledPartitionTree::overloaded_leaves`.
+    pub fn warn_overloaded_leaves(mut self, threshold: usize) -> Self {
+        self.overload_threshold = Some(threshold);
+        self
+    }
+
+    /// Builds a tree containing all of the given labeled polygons.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    pub fn build<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+    ) -> LabeledPartitionTree<T> {
+        self.build_with_timings(polygons).0
+    }
+
+    /// Builds a tree containing all of the given labeled polygons, recording where the build
+    /// spent its time.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    pub fn build_with_timings<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+    ) -> (LabeledPartitionTree<T>, BuildTimings) {
+        self.build_with_depths(polygons, &|_| self.max_depth)
+    }
+
+    /// Builds a tree containing all of the given labeled polygons, subdividing each cell until it
+    /// reaches the deepest depth wanted by any of the labels in it, instead of the builder's
+    /// `max_depth`.
+    ///
+    /// This targets depth where it pays off, e.g. subdividing cells with small, dense provinces
+    /// deeply while leaving cells covered only by vast, simple regions shallow.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `depth_for_label` - The maximum depth of the cells containing each label.
+    pub fn build_with_label_depths<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+        depth_for_label: impl Fn(&T) -> usize + Sync,
+    ) -> LabeledPartitionTree<T> {
+        self.build_with_depths(polygons, &depth_for_label).0
+    }
+
+    /// Builds a tree whose cells are subdivided down to the deepest depth given by
+    /// `depth_for_label` for their labels, recording where the build spent its time.
+    fn build_with_depths<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+        depth_for_label: &(dyn Fn(&T) -> usize + Sync),
+    ) -> (LabeledPartitionTree<T>, BuildTimings) {
+        let t0 = Instant::now();
+        let selected: Vec<T> = polygons.keys().cloned().collect();
+        let mut timings = BuildTimings::default();
+        let tree = LabeledPartitionTree::build_node(
+            self,
+            depth_for_label,
+            &selected,
+            &share_polygons(polygons),
+            self.root,
+            0,
+            &mut timings,
+        );
+        timings.total = t0.elapsed();
+        if let Some(threshold) = self.overload_threshold {
+            let overloaded = tree.overloaded_leaves(threshold);
+            if overloaded > 0 {
+                println!(
+                    "{overloaded} leaves exceed {threshold} polygons; consider increasing max_depth \
+                     (currently {}).",
+                    self.max_depth
+                );
+            }
+        }
+        (tree, timings)
+    }
+
+    /// Builds a tree containing all of the given labeled polygons plus a complement region with the
+    /// given label, which covers everything in the root bbox that is not in any polygon.
+    ///
+    /// For example, building a country tree with a `"HIGH_SEAS"` complement labels ocean points
+    /// with `"HIGH_SEAS"` instead of `None`.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `complement_label` - The label of the complement region. This should not already be used
+    ///                        by `polygons`; if it is, that label's polygon is replaced.
+    pub fn build_with_complement<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+        complement_label: T,
+    ) -> LabeledPartitionTree<T> {
+        let union = polygons
+            .values()
+            .fold(MultiPolygon::new(vec![]), |union, polygon| union.union(polygon));
+        let complement = MultiPolygon::from(self.root).difference(&union);
+
+        let mut polygons = polygons.clone();
+        polygons.insert(complement_label, complement);
+        self.build(&polygons)
+    }
+
+    /// Cheaply previews a build with this builder's options, e.g. to catch misconfigured or empty
+    /// data before starting a long build.
+    ///
+    /// The leaf count is estimated from the polygons' bounding boxes down to a shallow depth, and
+    /// assumes that cells occupied at that depth are fully subdivided below it. It is usually an
+    /// overestimate.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    pub fn plan_build<T: Clone + Eq + Hash>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+    ) -> BuildPlan {
+        let bboxes: Vec<Rect> = polygons
+            .values()
+            .filter_map(|polygon| polygon.bounding_rect())
+            .collect();
+        let data_bbox = bboxes.iter().copied().reduce(union_rect);
+        BuildPlan {
+            labels: polygons.len(),
+            polygons: polygons.values().map(|polygon| polygon.0.len()).sum(),
+            vertices: polygons.values().map(|polygon| polygon.coords_count()).sum(),
+            root: self.root,
+            data_bbox,
+            max_depth: self.max_depth,
+            estimated_leaves: self.estimate_leaves(self.root, &bboxes, 0),
+        }
+    }
+
+    /// Estimates the number of leaves under the cell at the given depth, given the bounding boxes
+    /// of the polygons that intersect it.
+    fn estimate_leaves(&self, cell: Rect, bboxes: &[Rect], depth: usize) -> usize {
+        if bboxes.is_empty() || depth == self.max_depth {
+            return 1;
+        }
+        let children = self.split.split(cell);
+        if depth == PLAN_DEPTH {
+            return children
+                .len()
+                .saturating_pow((self.max_depth - depth) as u32);
+        }
+        children
+            .into_iter()
+            .map(|child| {
+                let child_bboxes: Vec<Rect> = bboxes
+                    .iter()
+                    .filter(|bbox| bbox.intersects(&child))
+                    .copied()
+                    .collect();
+                self.estimate_leaves(child, &child_bboxes, depth + 1)
+            })
+            .sum()
+    }
+
+    /// Builds a tree at each candidate depth, measures its query throughput on the sample points,
+    /// and recommends the depth with the highest throughput.
+    ///
+    /// All other options are taken from this builder. Returns `None` if there are no candidates.
+    ///
+    /// # Arguments
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `sample_points` - Points representative of the expected queries.
+    /// * `candidate_depths` - The maximum depths to try.
+    pub fn recommend_max_depth<T: Clone + Eq + Hash + Send + Sync>(
+        &self,
+        polygons: &HashMap<T, MultiPolygon>,
+        sample_points: &[Point],
+        candidate_depths: &[usize],
+    ) -> Option<DepthRecommendation> {
+        let measurements: Vec<DepthMeasurement> = candidate_depths
+            .iter()
+            .map(|&max_depth| {
+                let t0 = Instant::now();
+                let tree = self.clone().max_depth(max_depth).build(polygons);
+                let build_time = t0.elapsed();
+
+                let t0 = Instant::now();
+                let matched = sample_points
+                    .iter()
+                    .filter(|point| tree.label_ref(point).is_some())
+                    .count();
+                std::hint::black_box(matched);
+                let query_time = t0.elapsed();
+
+                DepthMeasurement {
+                    max_depth,
+                    build_time,
+                    query_time,
+                    queries_per_second: sample_points.len() as f64 / query_time.as_secs_f64(),
+                    leaves: tree.size(),
+                }
+            })
+            .collect();
+
+        let max_depth = measurements
+            .iter()
+            .max_by(|a, b| {
+                a.queries_per_second
+                    .partial_cmp(&b.queries_per_second)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?
+            .max_depth;
+
+        Some(DepthRecommendation {
+            max_depth,
+            measurements,
+        })
+    }
+}
+
+/// A cheap preview of a build, from `TreeBuilder::plan_build`.
+#[derive(Clone, Debug)]
+pub struct BuildPlan {
+    /// The number of labels.
+    pub labels: usize,
+    /// The number of polygons across all labels.
+    pub polygons: usize,
+    /// The number of vertices across all polygons.
+    pub vertices: usize,
+    /// The bounding box covered by the root of the tree.
+    pub root: Rect,
+    /// The bounding box of all of the polygons, or `None` if there are none.
+    pub data_bbox: Option<Rect>,
+    /// The maximum depth of the tree.
+    pub max_depth: usize,
+    /// The estimated number of leaves in the tree.
+    pub estimated_leaves: usize,
+}
+
+/// The depth down to which `TreeBuilder::plan_build` follows the polygons' bounding boxes.
+const PLAN_DEPTH: usize = 4;
+
+/// A breakdown of the time spent building a tree, from `TreeBuilder::build_with_timings`.
+///
+/// Trees are built on several threads, so the times spent on each step are summed across threads
+/// and may add up to more than the total time.
+#[derive(Clone, Debug, Default)]
+pub struct BuildTimings {
+    /// The total time taken to build the tree.
+    pub total: Duration,
+    /// The time spent checking which polygons intersect each child cell.
+    pub prefilter: Duration,
+    /// The time spent clipping polygons to leaf cells at the maximum depth.
+    pub clip: Duration,
+    /// The number of nodes at each depth, starting from the root.
+    pub nodes_per_depth: Vec<usize>,
+}
+
+impl BuildTimings {
+    /// Adds the step times and node counts of a subtree's build to these timings.
+    fn add(&mut self, other: BuildTimings) {
+        self.prefilter += other.prefilter;
+        self.clip += other.clip;
+        if self.nodes_per_depth.len() < other.nodes_per_depth.len() {
+            self.nodes_per_depth.resize(other.nodes_per_depth.len(), 0);
+        }
+        for (depth, nodes) in other.nodes_per_depth.into_iter().enumerate() {
+            self.nodes_per_depth[depth] += nodes;
+        }
+    }
+}
+
+/// The measurements taken for one candidate depth by `TreeBuilder::recommend_max_depth`.
+#[derive(Clone, Debug)]
+pub struct DepthMeasurement {
+    /// The maximum depth of the measured tree.
+    pub max_depth: usize,
+    /// The time taken to build the tree.
+    pub build_time: Duration,
+    /// The time taken to query all of the sample points.
+    pub query_time: Duration,
+    /// The query throughput on the sample points.
+    pub queries_per_second: f64,
+    /// The number of leaves in the tree.
+    pub leaves: usize,
+}
+
+/// A recommended maximum depth, along with the measurements it was chosen from.
+#[derive(Clone, Debug)]
+pub struct DepthRecommendation {
+    /// The candidate depth with the highest query throughput.
+    pub max_depth: usize,
+    /// The measurements for every candidate depth, in the order the candidates were given.
+    pub measurements: Vec<DepthMeasurement>,
+}
+
+/// Returns the centers of the cells of a `columns` by `rows` grid over `area`, in row-major order
+/// starting from the top-left (minimum x, maximum y) cell.
+fn grid_centers(area: &Rect, columns: usize, rows: usize) -> Vec<Point> {
+    let cell_width = area.width() / columns as f64;
+    let cell_height = area.height() / rows as f64;
+    (0..rows)
+        .flat_map(|row| {
+            (0..columns).map(move |column| {
+                Point::new(
+                    area.min().x + (column as f64 + 0.5) * cell_width,
+                    area.max().y - (row as f64 + 0.5) * cell_height,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Wraps each region in an `Arc`, so leaves built without clipping can share them instead of
+/// storing copies.
+fn share_polygons<T: Clone + Eq + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+) -> HashMap<T, Arc<MultiPolygon>> {
+    polygons
+        .iter()
+        .map(|(label, polygon)| (label.clone(), Arc::new(polygon.clone())))
+        .collect()
+}
+
+/// Clips a region to a cell, e.g. to store it in a leaf.
+///
+/// Intersecting polygons is slow, so only the parts of the region that straddle the cell boundary
+/// are intersected with it; parts whose bounding boxes lie inside the cell are kept as they are,
+/// parts that contain the whole cell are replaced by the cell itself, and parts whose bounding
+/// boxes miss the cell are dropped.
+fn clip_to_cell(polygon: &MultiPolygon, cell: Rect) -> MultiPolygon {
+    let mut clipped = vec![];
+    for part in polygon.iter() {
+        let part_bbox = match part.bounding_rect() {
+            Some(part_bbox) => part_bbox,
+            None => continue,
+        };
+        if rect_contains_rect(&cell, &part_bbox) {
+            clipped.push(part.clone());
+        } else if !cell.intersects(&part_bbox) {
+            continue;
+        } else if part.contains(&cell) {
+            clipped.push(Polygon::from(cell));
+        } else {
+            clipped.extend(part.intersection(&Polygon::from(cell)));
+        }
+    }
+    MultiPolygon::new(clipped)
+}
+
+/// Returns whether `inner` lies inside `outer`, including on its boundary.
+fn rect_contains_rect(outer: &Rect, inner: &Rect) -> bool {
+    outer.min().x <= inner.min().x
+        && outer.min().y <= inner.min().y
+        && inner.max().x <= outer.max().x
+        && inner.max().y <= outer.max().y
+}
+
+/// Returns the number of heap bytes owned by a multipolygon's polygons and rings.
+fn multi_polygon_heap_bytes(polygon: &MultiPolygon) -> usize {
+    polygon.0.capacity() * size_of::<Polygon>()
+        + polygon
+            .0
+            .iter()
+            .map(|polygon| {
+                polygon.exterior().0.capacity() * size_of::<Coord>()
+                    + polygon.interiors().len() * size_of::<geo::LineString>()
+                    + polygon
+                        .interiors()
+                        .iter()
+                        .map(|ring| ring.0.capacity() * size_of::<Coord>())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
+/// Returns the overlap of the given rectangles, or `None` if their overlap has no area, e.g.
+/// because they only touch.
+fn intersect_rect(a: Rect, b: Rect) -> Option<Rect> {
+    let min = (a.min().x.max(b.min().x), a.min().y.max(b.min().y));
+    let max = (a.max().x.min(b.max().x), a.max().y.min(b.max().y));
+    if min.0 < max.0 && min.1 < max.1 {
+        Some(Rect::new(min, max))
+    } else {
+        None
+    }
+}
+
+/// Returns whether the flag is unset, e.g. to skip serializing unset flags.
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Returns the smallest rectangle containing both of the given rectangles.
+fn union_rect(a: Rect, b: Rect) -> Rect {
+    Rect::new(
+        (a.min().x.min(b.min().x), a.min().y.min(b.min().y)),
+        (a.max().x.max(b.max().x), a.max().y.max(b.max().y)),
+    )
+}
+
+/// Summary statistics describing the shape of a `LabeledPartitionTree`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeStats {
+    /// The total number of nodes, including leaves.
+    pub nodes: usize,
+    /// The number of leaf nodes.
+    pub leaves: usize,
+    /// The depth of the deepest leaf.
+    pub max_depth: usize,
+    /// The mean ratio of the longer to the shorter side of the non-degenerate leaf cells.
+    /// A value of 1 means every cell is square.
+    pub mean_leaf_aspect_ratio: f64,
+    /// Like `mean_leaf_aspect_ratio`, but with the width of each cell measured in degrees of
+    /// longitude scaled by the cosine of its central latitude, i.e. in proportion to its actual
+    /// east-west extent, as `SplitStrategy::LatitudeAware` does.
+    pub mean_leaf_ground_aspect_ratio: f64,
+}
+
+/// The labels of the cells of a regular grid over a bounding box, as computed by
+/// [`LabeledPartitionTree::rasterize`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelRaster<T> {
+    /// The bounding box covered by the raster.
+    pub bbox: Rect,
+    /// The number of columns in the raster.
+    pub width: usize,
+    /// The number of rows in the raster.
+    pub height: usize,
+    /// The labels of the cells in row-major order, starting from the top-left (north-west) cell.
+    pub cells: Vec<Option<T>>,
+}
+
+impl<T: Eq + Hash> LabelRaster<T> {
+    /// Returns the label of the cell at the given column and row, or `None` if the cell is
+    /// unlabeled or out of bounds. Row 0 is the northernmost row.
+    pub fn get(&self, column: usize, row: usize) -> Option<&T> {
+        if column >= self.width || row >= self.height {
+            return None;
+        }
+        self.cells[row * self.width + column].as_ref()
+    }
+
+    /// Returns the raster with each label replaced by a numeric id, assigned in the order the
+    /// labels first appear in the raster, e.g. for indexing into a color palette.
+    pub fn label_ids(&self) -> Vec<Option<usize>> {
+        let mut ids = HashMap::new();
+        self.cells
+            .iter()
+            .map(|cell| {
+                cell.as_ref().map(|label| {
+                    let next = ids.len();
+                    *ids.entry(label).or_insert(next)
+                })
+            })
+            .collect()
+    }
+
+    /// Saves the raster as an image with one pixel per cell, where each label gets its own color
+    /// and unlabeled cells are white.
+    ///
+    /// # Arguments
+    /// * `out_path` - The path where the resulting image will be saved.
+    #[cfg(feature = "plotting")]
+    pub fn plot(&self, out_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let size = (self.width as u32, self.height as u32);
+        let root = BitMapBackend::new(out_path, size).into_drawing_area();
+        root.fill(&WHITE)?;
+        for (index, id) in self.label_ids().into_iter().enumerate() {
+            if let Some(id) = id {
+                let pixel = ((index % self.width) as i32, (index / self.width) as i32);
+                root.draw_pixel(pixel, &Palette99::pick(id))?;
+            }
+        }
+        root.present()?;
+        Ok(())
+    }
+}
+
+/// Where two trees disagree, from `LabeledPartitionTree::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeDiff<T: Eq + Hash> {
+    /// The number of points that were sampled.
+    pub samples: usize,
+    /// The number of sampled points whose label changed, keyed by their label in the first tree
+    /// and their label in the second tree, where `None` means the point was unlabeled. Points
+    /// whose labels didn't change are not counted.
+    pub changes: HashMap<(Option<T>, Option<T>), usize>,
+}
+
+impl<T: Eq + Hash> TreeDiff<T> {
+    /// Returns the total number of sampled points whose labels changed.
+    pub fn changed_samples(&self) -> usize {
+        self.changes.values().sum()
+    }
+}
+
+/// Returns the label of a polygon containing the given point by checking every polygon, or `None`
+/// if no polygon contains it.
+///
+/// This is the naive method that `LabeledPartitionTree` speeds up. For a handful of regions or
+/// queries it is faster than building a tree at all, and it is useful as an oracle for checking
+/// tree results: if a tree and this function disagree on a point, the tree is at fault rather than
+/// the data. If several polygons contain the point, any of their labels may be returned.
+///
+/// # Arguments
+/// * `polygons` - A map of labels to their corresponding polygons.
+/// * `point` - The point to label.
+pub fn point_in_labeled_polygons<T: Clone + Eq + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+    point: &Point,
+) -> Option<T> {
+    polygons
+        .iter()
+        .find(|(_, polygon)| polygon.contains(point))
+        .map(|(label, _)| label.clone())
+}
+
+/// Returns the label of a polygon containing the given point by checking every polygon; see
+/// [`point_in_labeled_polygons`].
+///
+/// # Arguments
+/// * `polygons` - A map of labels to their corresponding polygons.
+/// * `point` - The point to label.
+#[cfg(feature = "testing")]
+pub fn brute_force_label<T: Clone + Eq + Hash>(
+    polygons: &HashMap<T, MultiPolygon>,
+    point: &Point,
+) -> Option<T> {
+    point_in_labeled_polygons(polygons, point)
+}
+
+/// The agreement between a tree and the polygons it should represent on random sample points, from
+/// `LabeledPartitionTree::accuracy_report`.
+#[cfg(feature = "testing")]
+#[derive(Clone, Debug)]
+pub struct AccuracyReport {
+    /// The number of sampled points.
+    pub samples: usize,
+    /// The sampled points where the tree and the reference polygons disagree.
+    pub disagreements: Vec<Point>,
+}
+
+#[cfg(feature = "testing")]
+impl AccuracyReport {
+    /// Returns the fraction of sampled points where the tree and the reference polygons agree.
+    pub fn agreement_rate(&self) -> f64 {
+        if self.samples == 0 {
+            1.0
+        } else {
+            1.0 - self.disagreements.len() as f64 / self.samples as f64
+        }
+    }
+}
+
+/// The number of candidate points tried by `LabeledPartitionTree::sample_point_in_label`.
+const SAMPLE_ATTEMPTS: usize = 10000;
+
+/// The number of concentric sample rings used by `LabeledPartitionTree::label_soft`.
+const SOFT_LABEL_RINGS: usize = 4;
+
+/// An error in the geometry used to answer a query, from `LabeledPartitionTree::try_label`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryError {
+    /// The query point has a NaN or infinite coordinate.
+    NonFinitePoint,
+    /// A polygon in the leaf with the given cell has a NaN or infinite coordinate.
+    NonFiniteCoordinate { cell: Rect },
+    /// A polygon in the leaf with the given cell has a ring with too few vertices to enclose an
+    /// area.
+    DegenerateRing { cell: Rect, vertices: usize },
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::NonFinitePoint => write!(f, "the query point has a non-finite coordinate"),
+            QueryError::NonFiniteCoordinate { cell } => write!(
+                f,
+                "a polygon in the leaf covering {:?} has a non-finite coordinate",
+                cell
+            ),
+            QueryError::DegenerateRing { cell, vertices } => write!(
+                f,
+                "a polygon in the leaf covering {:?} has a ring with only {} vertices",
+                cell, vertices
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Checks that a polygon stored in the leaf with the given cell can be used to answer queries.
+fn validate_leaf_polygon(polygon: &MultiPolygon, cell: Rect) -> Result<(), QueryError> {
+    for part in polygon.iter() {
+        for ring in std::iter::once(part.exterior()).chain(part.interiors()) {
+            // a closed ring needs at least three distinct vertices plus the closing vertex
+            if ring.0.len() < 4 {
+                return Err(QueryError::DegenerateRing {
+                    cell,
+                    vertices: ring.0.len(),
+                });
+            }
+            if ring.0.iter().any(|coord| !coord.x.is_finite() || !coord.y.is_finite()) {
+                return Err(QueryError::NonFiniteCoordinate { cell });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A broken invariant found by `LabeledPartitionTree::validate`. Each error identifies a node by
+/// the indices of the children followed to reach it from the root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The node's bounding box is not contained in its parent's.
+    ChildOutsideParent { path: Vec<usize> },
+    /// The node has children but also stores polygons, which queries never check.
+    InternalNodeWithPolygons { path: Vec<usize> },
+    /// The node's children have gaps between them or overlap each other.
+    ChildrenDoNotTile { path: Vec<usize> },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::ChildOutsideParent { path } => {
+                write!(f, "the node at {:?} is not inside its parent", path)
+            }
+            ValidationError::InternalNodeWithPolygons { path } => {
+                write!(f, "the node at {:?} has both children and polygons", path)
+            }
+            ValidationError::ChildrenDoNotTile { path } => write!(
+                f,
+                "the children of the node at {:?} have gaps or overlaps",
+                path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A compact serializable form of a `LabeledPartitionTree`; see `LabeledPartitionTree::to_compact`.
+///
+/// Most child cells' bounding boxes are determined by their parent's bounding box and the rule
+/// used to split it, so they are omitted and recomputed when the tree is reconstructed. This
+/// shrinks serialized trees, especially deep ones, without slowing down queries on the
+/// reconstructed tree.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct CompactTree<T: Eq + Hash> {
+    split: SplitStrategy,
+    bbox: Rect,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    altitudes: HashMap<T, (f64, f64)>,
+    root: CompactNode<T>,
+}
+
+/// A node of a `CompactTree`, which only stores its bounding box if it can't be recomputed.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct CompactNode<T: Eq + Hash> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bbox: Option<Rect>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<CompactNode<T>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    polygons: HashMap<T, LeafPolygon>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    overlapping: bool,
+}
+
+/// Reusable state for a batch of queries on a `LabeledPartitionTree`; see
+/// `LabeledPartitionTree::label_with_ctx`.
+///
+/// The context remembers the cells the previous query descended through, so a query for a nearby
+/// point (e.g. the next point of a grid or a track) resumes from the deepest of those cells that
+/// still contains it instead of starting over at the root. Its buffer is reused, so queries don't
+/// allocate once it has grown to the depth of the tree.
+pub struct QueryContext<'a, T: Eq + Hash> {
+    /// The cells from the root to the leaf reached by the previous query.
+    path: Vec<&'a LabeledPartitionTree<T>>,
+}
+
+impl<T: Eq + Hash> QueryContext<'_, T> {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        QueryContext { path: vec![] }
+    }
+}
+
+impl<T: Eq + Hash> Default for QueryContext<'_, T> {
+    fn default() -> Self {
+        QueryContext::new()
+    }
+}
+
+/// A location that can be used to query a tree, e.g. a `Point`, a `Coord`, or an `(x, y)` tuple of
+/// longitude and latitude.
+pub trait QueryPoint {
+    /// Returns the location as a point.
+    fn to_point(&self) -> Point;
+}
+
+impl QueryPoint for Point {
+    fn to_point(&self) -> Point {
+        *self
+    }
+}
+
+impl QueryPoint for Coord {
+    fn to_point(&self) -> Point {
+        Point::from(*self)
+    }
+}
+
+impl QueryPoint for (f64, f64) {
+    fn to_point(&self) -> Point {
+        Point::from(*self)
+    }
+}
+
+impl<P: QueryPoint + ?Sized> QueryPoint for &P {
+    fn to_point(&self) -> Point {
+        (**self).to_point()
+    }
+}
+
+/// The region of a label in a leaf.
+///
+/// Leaves own the polygons clipped to their cells, while leaves of trees built without clipping
+/// share the whole source polygons instead of each storing a copy. Both are serialized as plain
+/// polygons, so loaded trees own all of their polygons.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub(crate) enum LeafPolygon {
+    Owned(MultiPolygon),
+    Shared(Arc<MultiPolygon>),
+}
+
+impl Deref for LeafPolygon {
+    type Target = MultiPolygon;
+
+    fn deref(&self) -> &MultiPolygon {
+        match self {
+            LeafPolygon::Owned(polygon) => polygon,
+            LeafPolygon::Shared(polygon) => polygon,
+        }
+    }
+}
+
+/// A struct representing a labeled partition tree.
+///
+/// This structure is used for performing fast point-in-polygon queries by recursively checking 
+/// bounding boxes before performing the final point-in-polygon check.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LabeledPartitionTree<T: Eq + Hash> {
+    pub(crate) children: Box<Vec<LabeledPartitionTree<T>>>,
+    pub(crate) polygons: HashMap<T, LeafPolygon>,
+    pub(crate) bbox: Rect,
+    /// Whether the cells of the node's children may overlap, e.g. at the root of a merged tree.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) overlapping: bool,
+    /// The altitude band of each label, only set on the root; see `with_altitude_bands`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    altitudes: HashMap<T, (f64, f64)>,
+}
+
+impl<T: Clone + Eq + Hash> LabeledPartitionTree<T> {
+    /// Constructs a labeled partition tree from a set of labeled polygons.
+    ///
+    /// # Arguments
+    /// * `selected` - The labels of the polygons to be included in the tree.
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `bbox` - The bounding box for the current partition.
+    /// * `max_depth` - The maximum depth of the tree. Deeper trees tend to result in faster queries, 
+    ///                 but take much longer to construct.
+    /// * `depth` - The current depth during recursion.
+    pub fn from_labeled_polygons(
+        selected: &Vec<T>,
+        polygons: &HashMap<T, MultiPolygon>,
+        bbox: Rect,
+        max_depth: usize,
+        depth: usize,
+    ) -> LabeledPartitionTree<T>
+    where
+        T: Send + Sync,
+    {
+        LabeledPartitionTree::from_labeled_polygons_with_split(
+            selected,
+            polygons,
+            bbox,
+            max_depth,
+            depth,
+            SplitStrategy::Quadrants,
+        )
+    }
+
+    /// Constructs a labeled partition tree from a set of labeled polygons, splitting cells according
+    /// to the given strategy.
+    ///
+    /// # Arguments
+    /// * `selected` - The labels of the polygons to be included in the tree.
+    /// * `polygons` - A map of labels to their corresponding polygons.
+    /// * `bbox` - The bounding box for the current partition.
+    /// * `max_depth` - The maximum depth of the tree.
+    /// * `depth` - The current depth during recursion.
+    /// * `split` - The rule used to split cells into child cells.
+    pub fn from_labeled_polygons_with_split(
+        selected: &Vec<T>,
+        polygons: &HashMap<T, MultiPolygon>,
+        bbox: Rect,
+        max_depth: usize,
+        depth: usize,
+        split: SplitStrategy,
+    ) -> LabeledPartitionTree<T>
+    where
+        T: Send + Sync,
+    {
+        let builder = TreeBuilder::new().max_depth(max_depth).branching(split);
+        let mut timings = BuildTimings::default();
+        LabeledPartitionTree::build_node(
+            &builder,
+            &|_| max_depth,
+            selected,
+            &share_polygons(polygons),
+            bbox,
+            depth,
+            &mut timings,
+        )
+    }
+
+    /// Recursively builds the node covering `bbox` at the given depth using the builder's options,
+    /// adding the time spent to `timings`. Cells are subdivided until they reach the deepest depth
+    /// that `depth_for_label` gives for their labels.
+    fn build_node(
+        builder: &TreeBuilder,
+        depth_for_label: &(dyn Fn(&T) -> usize + Sync),
+        selected: &Vec<T>,
+        polygons: &HashMap<T, Arc<MultiPolygon>>,
+        bbox: Rect,
+        depth: usize,
+        timings: &mut BuildTimings,
+    ) -> LabeledPartitionTree<T>
+    where
+        T: Send + Sync,
+    {
+        if timings.nodes_per_depth.len() <= depth {
+            timings.nodes_per_depth.resize(depth + 1, 0);
+        }
+        timings.nodes_per_depth[depth] += 1;
+
+        let outside_mask = match &builder.mask {
+            Some(mask) => !bbox.intersects(mask),
+            None => false,
+        };
+        let (children, inner_polygons) = if outside_mask {
+            (Box::new(vec![]), HashMap::new())
+        } else if depth >= selected.iter().map(depth_for_label).max().unwrap_or(0) {
+            let t0 = Instant::now();
+            let leaf_polygons = selected
+                .iter()
+                .map(|label| {
+                    let polygon = polygons.get(label).unwrap();
+                    if builder.clip {
+                        let clipped = clip_to_cell(polygon, bbox);
+                        (label.clone(), LeafPolygon::Owned(clipped))
+                    } else {
+                        (label.clone(), LeafPolygon::Shared(Arc::clone(polygon)))
+                    }
+                })
+                .collect();
+            timings.clip += t0.elapsed();
+            (Box::new(vec![]), leaf_polygons)
+        } else if selected.len() == 0 {
+            (Box::new(vec![]), HashMap::new())
+        } else if selected.len() == 1 && polygons.get(&selected[0]).unwrap().contains(&bbox) {
+            // TODO the check for this is slow
+            (
+                Box::new(vec![]),
+                vec![(selected[0].clone(), LeafPolygon::Owned(bbox.into()))]
+                    .into_iter()
+                    .collect(),
+            )
+        } else {
+            // TODO check if a different branching factor can speed things up
+            let bboxes = builder.split.split(bbox);
+
+            let t0 = Instant::now();
+            let bbox_selected_polygons: Vec<Vec<T>> = bboxes
+                .iter()
+                .map(|bbox| {
+                    // TODO it might be possible to speed up this intersection check
+                    selected
+                        .iter()
+                        .filter(|&label| bbox.intersects(&**polygons.get(label).unwrap()))
+                        .cloned()
+                        .collect()
+                })
+                .collect();
+            timings.prefilter += t0.elapsed();
+
+            // with the `parallel` feature, subtrees are built on rayon's work-stealing pool, so idle
+            // threads pick up the deeper splits of crowded subtrees instead of waiting on them
+            #[cfg(feature = "parallel")]
+            let selected_per_child = bbox_selected_polygons.par_iter();
+            #[cfg(not(feature = "parallel"))]
+            let selected_per_child = bbox_selected_polygons.iter();
+            let children: Vec<(LabeledPartitionTree<T>, BuildTimings)> = selected_per_child
+                .zip(bboxes)
+                .map(|(selected, bbox)| {
+                    let mut child_timings = BuildTimings::default();
+                    let child = LabeledPartitionTree::build_node(
+                        builder,
+                        depth_for_label,
+                        selected,
+                        polygons,
+                        bbox,
+                        depth + 1,
+                        &mut child_timings,
+                    );
+                    (child, child_timings)
+                })
+                .collect();
+
+            (
+                Box::new(
+                    children
+                        .into_iter()
+                        .map(|(child, child_timings)| {
+                            timings.add(child_timings);
+                            child
+                        })
+                        .collect(),
+                ),
+                HashMap::new(),
+            )
+        };
+
+        LabeledPartitionTree {
+            children,
+            bbox,
+            polygons: inner_polygons,
+            overlapping: false,
+            altitudes: HashMap::new(),
+        }
+    }
+
+    /// Combines trees covering different regions into a single tree.
+    ///
+    /// The given trees become the children of a new root whose bounding box covers all of theirs.
+    /// Where the trees overlap, queries try each tree containing the query point in order until
+    /// one labels it, so earlier trees take precedence.
+    /// The altitude bands of the given trees are combined. Merging no trees produces a tree that
+    /// labels nothing.
+    ///
+    /// # Arguments
+    /// * `trees` - The trees to combine.
+    pub fn merge(trees: Vec<LabeledPartitionTree<T>>) -> LabeledPartitionTree<T> {
+        let bbox = trees
+            .iter()
+            .map(|tree| tree.bbox)
+            .reduce(union_rect)
+            .unwrap_or(Rect::new((0.0, 0.0), (0.0, 0.0)));
+        let altitudes = trees
+            .iter()
+            .flat_map(|tree| tree.altitudes.iter())
+            .map(|(label, band)| (label.clone(), *band))
+            .collect();
+        let overlapping = trees.iter().enumerate().any(|(i, a)| {
+            trees[i + 1..]
+                .iter()
+                .any(|b| intersect_rect(a.bbox, b.bbox).is_some())
+        });
+
+        LabeledPartitionTree {
+            children: Box::new(trees),
+            bbox,
+            polygons: HashMap::new(),
+            overlapping,
+            altitudes,
+        }
+    }
+
+    /// Returns the portion of the tree overlapping `window`, re-rooted at the part of `window`
+    /// inside the tree's root cell.
+    ///
+    /// Leaves entirely outside the window, or that only touch its edge, are dropped. Leaves
+    /// straddling its edge keep their polygons as they are, so queries strictly inside the window
+    /// give the same results as on the full tree, but their cells are clipped to the window like
+    /// those of every other node, so the subtree's children tile its root and it passes
+    /// [`LabeledPartitionTree::validate`]. This is useful for serializing and shipping only the
+    /// part of a large tree that is actually needed.
+    ///
+    /// # Arguments
+    /// * `window` - The region the subtree should cover.
+    pub fn subtree_for(&self, window: Rect) -> LabeledPartitionTree<T> {
+        let window = intersect_rect(self.bbox, window).unwrap_or(window);
+        let (children, polygons) = if self.children.is_empty() {
+            (vec![], self.polygons.clone())
+        } else {
+            (
+                self.children
+                    .iter()
+                    .filter_map(|child| child.prune_to(&window))
+                    .collect(),
+                HashMap::new(),
+            )
+        };
+
+        LabeledPartitionTree {
+            children: Box::new(children),
+            bbox: window,
+            polygons,
+            overlapping: self.overlapping,
+            altitudes: self.altitudes.clone(),
+        }
+    }
+
+    /// Returns a copy of this node clipped to `window`, without the descendants that do not
+    /// overlap it, or `None` if nothing overlaps it.
+    fn prune_to(&self, window: &Rect) -> Option<LabeledPartitionTree<T>> {
+        let bbox = intersect_rect(self.bbox, *window)?;
+        if self.children.is_empty() {
+            Some(LabeledPartitionTree {
+                children: Box::new(vec![]),
+                bbox,
+                polygons: self.polygons.clone(),
+                overlapping: false,
+                altitudes: HashMap::new(),
+            })
+        } else {
+            let children: Vec<LabeledPartitionTree<T>> = self
+                .children
+                .iter()
+                .filter_map(|child| child.prune_to(window))
+                .collect();
+            if children.is_empty() {
+                None
+            } else {
+                Some(LabeledPartitionTree {
+                    children: Box::new(children),
+                    bbox,
+                    polygons: HashMap::new(),
+                    overlapping: self.overlapping,
+                    altitudes: HashMap::new(),
+                })
+            }
+        }
+    }
+
+    /// Returns the label of the partition that contains the given point.
+    ///
+    /// This method recursively searches for the leaf node that contains the point and returns its label.
+    /// If no leaf node contains the point, `None` is returned.
+    /// 
+    /// # Arguments
+    /// * `point` - The point to check, e.g. a `Point`, a `Coord`, or an `(x, y)` tuple.
+    pub fn label(&self, point: impl QueryPoint) -> Option<T> {
+        self.label_ref(point).cloned()
+    }
+
+    /// Returns the label of the partition that contains the center of the given H3 cell.
+    ///
+    /// # Arguments
+    /// * `cell` - The H3 cell to label.
+    #[cfg(feature = "h3")]
+    pub fn label_h3(&self, cell: CellIndex) -> Option<T> {
+        let center = LatLng::from(cell);
+        self.label(&Point::new(center.lng(), center.lat()))
+    }
+
+    /// Returns the label of the partition that contains the center of each of the given H3 cells,
+    /// in the same order as the cells.
+    ///
+    /// # Arguments
+    /// * `cells` - The H3 cells to label.
+    #[cfg(feature = "h3")]
+    pub fn label_h3_cells(&self, cells: &[CellIndex]) -> Vec<Option<T>> {
+        cells.iter().map(|&cell| self.label_h3(cell)).collect()
+    }
+
+    /// Labels a batch of points, calling `f` with the index of each point and a reference to its
+    /// label as it goes rather than collecting the labels, e.g. for stream processing.
+    ///
+    /// # Arguments
+    /// * `points` - The points to label.
+    /// * `f` - Called with the index and label of each point, in order.
+    pub fn for_each_label<P: QueryPoint>(
+        &self,
+        points: &[P],
+        mut f: impl FnMut(usize, Option<&T>),
+    ) {
+        let mut ctx = QueryContext::new();
+        for (index, point) in points.iter().enumerate() {
+            f(index, self.label_ref_with_ctx(point, &mut ctx));
+        }
+    }
+
+    /// Labels a batch of points and groups them by label, returning the indices of the points with
+    /// each label along with the indices of the points that no region contains.
+    ///
+    /// Indices are in ascending order within each group.
+    ///
+    /// # Arguments
+    /// * `points` - The points to label.
+    pub fn group_by_label<P: QueryPoint>(
+        &self,
+        points: &[P],
+    ) -> (HashMap<T, Vec<usize>>, Vec<usize>) {
+        let mut groups: HashMap<T, Vec<usize>> = HashMap::new();
+        let mut misses = vec![];
+        let mut ctx = QueryContext::new();
+        for (index, point) in points.iter().enumerate() {
+            match self.label_ref_with_ctx(point, &mut ctx) {
+                Some(label) => groups.entry(label.clone()).or_insert(Vec::new()).push(index),
+                None => misses.push(index),
+            }
+        }
+        (groups, misses)
+    }
+
+    /// Returns the label of the partition that contains the given latitude and longitude, after
+    /// wrapping the longitude into [-180, 180).
+    ///
+    /// This handles longitudes that have accumulated past the antimeridian, e.g. from panning a
+    /// web map, so 200° is treated as -160°. Latitudes outside [-90, 90] and non-finite
+    /// coordinates are invalid and label nothing.
+    ///
+    /// # Arguments
+    /// * `lat` - The latitude, in degrees.
+    /// * `lon` - The longitude, in degrees; any finite value is accepted.
+    pub fn label_wrapped(&self, lat: f64, lon: f64) -> Option<T> {
+        if !lat.is_finite() || !lon.is_finite() || lat.abs() > 90.0 {
+            return None;
+        }
+        let lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+        self.label(&Point::new(lon, lat))
+    }
+
+    /// Returns the label of the partition that contains the given point, considering only regions
+    /// whose labels are in `allowed`, e.g. to share one tree between clients that may only see
+    /// certain regions.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `allowed` - The labels that may be returned.
+    pub fn label_within(&self, point: impl QueryPoint, allowed: &HashSet<T>) -> Option<T> {
+        let point = &point.to_point();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.children.is_empty() {
+                if let Some((label, _)) = node.polygons.iter().find(|(label, polygon)| {
+                    allowed.contains(*label) && polygon.contains(point)
+                }) {
+                    return Some(label.clone());
+                }
+            } else {
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|child| child.bbox.contains(point)),
+                );
+            }
+        }
+        None
+    }
+
+    /// Returns the label of the partition that contains the point with the given raw coordinates,
+    /// where `x` is the longitude and `y` is the latitude.
+    ///
+    /// This behaves like [`LabeledPartitionTree::label`], but compares the coordinates against
+    /// cell bounding boxes directly and only constructs a `Point` for the final point-in-polygon
+    /// checks, which saves a little time per query in tight loops.
+    ///
+    /// # Arguments
+    /// * `x` - The longitude of the point.
+    /// * `y` - The latitude of the point.
+    pub fn label_xy(&self, x: f64, y: f64) -> Option<T> {
+        let mut node = self;
+        while !node.children.is_empty() {
+            // the same strict comparisons as `Rect::contains`, so points on cell boundaries
+            // are handled exactly like in `label`
+            node = node.children.iter().find(|child| {
+                let (min, max) = (child.bbox.min(), child.bbox.max());
+                x > min.x && x < max.x && y > min.y && y < max.y
+            })?;
+        }
+        node.leaf_label(&Point::new(x, y)).cloned()
+    }
+
+    /// Returns the label of the partition that contains the given point, copying it out of the
+    /// tree.
+    ///
+    /// This behaves like [`LabeledPartitionTree::label`], but is clearer for `Copy` labels such as
+    /// integer region ids.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn label_copy(&self, point: impl QueryPoint) -> Option<T>
+    where
+        T: Copy,
+    {
+        self.label_ref(point).copied()
+    }
+
+    /// Returns the label of the partition that contains the given point, or an error if the
+    /// geometry needed to answer the query is malformed.
+    ///
+    /// If `validate` is set, the query point and every polygon in the leaves checked while
+    /// answering the query are validated, and malformed geometry that could make the result
+    /// wrong is reported instead of being silently used. Otherwise, this behaves like `label`.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `validate` - Whether to validate the geometry used to answer the query.
+    pub fn try_label(
+        &self,
+        point: impl QueryPoint,
+        validate: bool,
+    ) -> Result<Option<T>, QueryError> {
+        if !validate {
+            return Ok(self.label(point));
+        }
+        let point = &point.to_point();
+        if !point.x().is_finite() || !point.y().is_finite() {
+            return Err(QueryError::NonFinitePoint);
+        }
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.children.is_empty() {
+                for polygon in node.polygons.values() {
+                    validate_leaf_polygon(polygon, node.bbox)?;
+                }
+                if let Some((label, _)) =
+                    node.polygons.iter().find(|(_, polygon)| polygon.contains(point))
+                {
+                    return Ok(Some(label.clone()));
+                }
+            } else {
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|child| child.bbox.contains(point)),
+                );
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns a reference to the label of the partition that contains the given point.
+    ///
+    /// This behaves like [`LabeledPartitionTree::label`], but borrows the label from the tree
+    /// instead of cloning it.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn label_ref(&self, point: impl QueryPoint) -> Option<&T> {
+        let point = &point.to_point();
+        // the query walks down a single path rather than recursing so that deep trees can't
+        // overflow the call stack; sibling cells only share boundaries, and `Rect::contains`
+        // excludes boundaries, so at most one child contains the point and nothing is allocated
+        let mut node = self;
+        while !node.children.is_empty() {
+            if node.overlapping {
+                return node.label_ref_backtracking(point);
+            }
+            node = node
+                .children
+                .iter()
+                .find(|child| child.bbox.contains(point))?;
+        }
+        node.leaf_label(point)
+    }
+
+    /// Returns the label of the partition that contains the given point, searching every child
+    /// that contains it in order until one labels it, e.g. below a node whose children overlap.
+    fn label_ref_backtracking(&self, point: &Point) -> Option<&T> {
+        // children are pushed in reverse so they are visited in order
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.children.is_empty() {
+                if let Some(label) = node.leaf_label(point) {
+                    return Some(label);
+                }
+            } else {
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|child| child.bbox.contains(point)),
+                );
+            }
+        }
+        None
+    }
+
+    /// Returns the label of the polygon in this leaf that contains the given point, if any.
+    fn leaf_label(&self, point: &Point) -> Option<&T> {
+        self.polygons
+            .iter()
+            .find(|(_, polygon)| polygon.contains(point))
+            .map(|(label, _)| label)
+    }
+
+    /// Returns the label of the partition that contains the given point, reusing the given
+    /// context so that batches of nearby queries skip most of the descent and don't allocate,
+    /// e.g. in a hot loop. Each thread should keep its own context.
+    ///
+    /// The results are the same as those of [`LabeledPartitionTree::label`]. Queries below nodes
+    /// whose children overlap, like the roots of merged trees, search those children in order as
+    /// `label` does instead of resuming the previous path.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `ctx` - The context to reuse across queries on this tree.
+    pub fn label_with_ctx<'a>(
+        &'a self,
+        point: impl QueryPoint,
+        ctx: &mut QueryContext<'a, T>,
+    ) -> Option<T> {
+        self.label_ref_with_ctx(point, ctx).cloned()
+    }
+
+    /// Returns a reference to the label of the partition that contains the given point, reusing
+    /// the given context; see [`LabeledPartitionTree::label_with_ctx`].
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `ctx` - The context to reuse across queries on this tree.
+    pub fn label_ref_with_ctx<'a>(
+        &'a self,
+        point: impl QueryPoint,
+        ctx: &mut QueryContext<'a, T>,
+    ) -> Option<&'a T> {
+        let point = &point.to_point();
+        let path = &mut ctx.path;
+        match path.first() {
+            Some(root) if std::ptr::eq(*root, self) => {}
+            // the context is new or was last used with another tree
+            _ => {
+                path.clear();
+                path.push(self);
+            }
+        }
+        // every cell containing the point lies on the path `label_ref` would take to it, so the
+        // descent can resume from the deepest cell of the previous path that contains the point
+        while path.len() > 1 && !path[path.len() - 1].bbox.contains(point) {
+            path.pop();
+        }
+        let mut node = path[path.len() - 1];
+        while !node.children.is_empty() {
+            // the path never goes below a node with overlapping children, so every cell above
+            // `node` is the only one of its siblings that contains the point
+            if node.overlapping {
+                return node.label_ref_backtracking(point);
+            }
+            node = node
+                .children
+                .iter()
+                .find(|child| child.bbox.contains(point))?;
+            path.push(node);
+        }
+        node.leaf_label(point)
+    }
+
+    /// Returns the bounding boxes of the cells from the root to the leaf that answers a query for
+    /// the given point, e.g. to see how the search was narrowed down.
+    ///
+    /// If no leaf labels the point, the path ends at the first leaf checked, which is where the
+    /// point would have to be for the tree to label it.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn path_to_leaf(&self, point: impl QueryPoint) -> Vec<Rect> {
+        let point = &point.to_point();
+        let mut first_leaf = None;
+        self.path_to_labeling_leaf(point, &mut vec![], &mut first_leaf)
+            .or(first_leaf)
+            .unwrap_or_default()
+    }
+
+    /// Returns the path from this node to the leaf that labels the point, if any, keeping the
+    /// path to the first leaf checked in `first_leaf`.
+    fn path_to_labeling_leaf(
+        &self,
+        point: &Point,
+        path: &mut Vec<Rect>,
+        first_leaf: &mut Option<Vec<Rect>>,
+    ) -> Option<Vec<Rect>> {
+        path.push(self.bbox);
+        let result = if self.children.is_empty() {
+            if self.polygons.values().any(|polygon| polygon.contains(point)) {
+                Some(path.clone())
+            } else {
+                if first_leaf.is_none() {
+                    *first_leaf = Some(path.clone());
+                }
+                None
+            }
+        } else {
+            self.children
+                .iter()
+                .filter(|child| child.bbox.contains(point))
+                .find_map(|child| child.path_to_labeling_leaf(point, path, first_leaf))
+        };
+        path.pop();
+        result
+    }
+
+    /// Returns the label of the partition that contains the given point along with the indices of
+    /// the source features that contributed to that label.
+    ///
+    /// The tree does not record which of a label's features a point fell in, so all of the
+    /// label's source features are returned.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `sources` - A map of labels to their source feature indices, e.g. from
+    ///               `datasets::labeled_polygons_with_sources`.
+    pub fn label_with_source<'a>(
+        &self,
+        point: &Point,
+        sources: &'a HashMap<T, Vec<usize>>,
+    ) -> Option<(T, &'a [usize])> {
+        let label = self.label_ref(point)?;
+        let indices = sources.get(label).map(|indices| indices.as_slice()).unwrap_or(&[]);
+        Some((label.clone(), indices))
+    }
+
+    /// Sets the altitude band of each label, for datasets where regions are stacked at different
+    /// altitudes (e.g. airspace sectors). The bands are used by `label_3d`.
+    ///
+    /// # Arguments
+    /// * `bands` - A map of labels to the `(min, max)` altitudes of their regions, inclusive.
+    ///             Labels without a band span all altitudes.
+    pub fn with_altitude_bands(mut self, bands: HashMap<T, (f64, f64)>) -> Self {
+        self.altitudes = bands;
+        self
+    }
+
+    /// Returns the label of the region that contains the given point and whose altitude band
+    /// contains `z`.
+    ///
+    /// The 2D partitioning is unchanged; altitudes only filter the regions in the matching leaf.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `z` - The altitude of the point, in the units of the altitude bands.
+    pub fn label_3d(&self, point: &Point, z: f64) -> Option<T> {
+        self.label_3d_in_bands(point, z, &self.altitudes)
+    }
+
+    /// Recursively searches for a region containing the point whose band in `bands` contains `z`.
+    fn label_3d_in_bands(&self, point: &Point, z: f64, bands: &HashMap<T, (f64, f64)>) -> Option<T> {
+        if self.children.is_empty() {
+            self.polygons.iter().find_map(|(label, polygon)| {
+                let in_band = bands
+                    .get(label)
+                    .map_or(true, |&(min, max)| min <= z && z <= max);
+                if in_band && polygon.contains(point) {
+                    Some(label.clone())
+                } else {
+                    None
+                }
+            })
+        } else {
+            self.children
+                .iter()
+                .filter(|child| child.bbox.contains(point))
+                .find_map(|child| child.label_3d_in_bands(point, z, bands))
+        }
+    }
+
+    /// Returns whether the two given points fall in the same labeled region.
+    ///
+    /// Labels are compared by reference, so nothing is cloned. Points that are not in any region
+    /// are never considered to be in the same region.
+    ///
+    /// # Arguments
+    /// * `a` - The first point to check.
+    /// * `b` - The second point to check.
+    pub fn same_region(&self, a: &Point, b: &Point) -> bool {
+        match (self.label_ref(a), self.label_ref(b)) {
+            (Some(a_label), Some(b_label)) => a_label == b_label,
+            _ => false,
+        }
+    }
+
+    /// Returns a soft assignment of the given point to the regions around it.
+    ///
+    /// The tree is queried at a fixed pattern of samples on concentric rings within `radius` of the
+    /// point, and each label is weighted by the fraction of samples that fall inside its region.
+    /// A point deep inside one region gets that region with weight ~1, while a point near a
+    /// tri-border gets all three regions with fractional weights. Samples that fall outside every
+    /// region (e.g. over the ocean) make the weights sum to less than 1.
+    ///
+    /// Results are ordered by descending weight.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `radius` - The radius of the sampled neighborhood, in degrees.
+    pub fn label_soft(&self, point: &Point, radius: f64) -> Vec<(T, f64)> {
+        let mut samples = vec![*point];
+        for ring in 1..=SOFT_LABEL_RINGS {
+            let ring_radius = radius * ring as f64 / SOFT_LABEL_RINGS as f64;
+            let ring_samples = 8 * ring;
+            for i in 0..ring_samples {
+                let angle = std::f64::consts::TAU * i as f64 / ring_samples as f64;
+                samples.push(Point::new(
+                    point.x() + ring_radius * angle.cos(),
+                    point.y() + ring_radius * angle.sin(),
+                ));
+            }
+        }
+
+        let mut counts: HashMap<T, usize> = HashMap::new();
+        samples.iter().for_each(|sample| {
+            if let Some(label) = self.label(sample) {
+                *counts.entry(label).or_insert(0) += 1;
+            }
+        });
+
+        let mut weights: Vec<(T, f64)> = counts
+            .into_iter()
+            .map(|(label, count)| (label, count as f64 / samples.len() as f64))
+            .collect();
+        weights.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        weights
+    }
+
+    /// Estimates which regions cover an area by labeling a regular grid of sample points inside it.
+    ///
+    /// Returns the number of samples that fell in each labeled region; samples outside every
+    /// region, including samples in a region's holes (e.g. lakes), are not counted. The number of
+    /// samples is rounded down to the nearest square so that the grid has as many rows as columns.
+    ///
+    /// # Arguments
+    /// * `area` - The area to sample.
+    /// * `samples` - The approximate number of points to sample.
+    pub fn area_label_histogram(&self, area: &Rect, samples: usize) -> HashMap<T, usize> {
+        let side = (samples as f64).sqrt().floor() as usize;
+        let mut histogram = HashMap::new();
+        let mut ctx = QueryContext::new();
+        grid_centers(area, side, side).iter().for_each(|point| {
+            if let Some(label) = self.label_with_ctx(point, &mut ctx) {
+                *histogram.entry(label).or_insert(0) += 1;
+            }
+        });
+        histogram
+    }
+
+    /// Labels the center of every cell of a regular grid over the given bounding box, e.g. to
+    /// visualize the tree's coverage with [`LabelRaster::plot`].
+    ///
+    /// # Arguments
+    /// * `bbox` - The bounding box to cover.
+    /// * `width` - The number of columns in the grid.
+    /// * `height` - The number of rows in the grid.
+    pub fn rasterize(&self, bbox: Rect, width: usize, height: usize) -> LabelRaster<T> {
+        let mut ctx = QueryContext::new();
+        let cells = grid_centers(&bbox, width, height)
+            .iter()
+            .map(|point| self.label_with_ctx(point, &mut ctx))
+            .collect();
+        LabelRaster {
+            bbox,
+            width,
+            height,
+            cells,
+        }
+    }
+
+    /// Compares the labels of this tree and another tree, e.g. one built from a newer version of
+    /// the same data, on an evenly spaced grid of points covering both trees' root cells.
+    ///
+    /// The number of samples is rounded down to the nearest square so that the grid has as many
+    /// rows as columns.
+    ///
+    /// # Arguments
+    /// * `other` - The tree to compare with.
+    /// * `samples` - The approximate number of points to sample.
+    pub fn diff(&self, other: &LabeledPartitionTree<T>, samples: usize) -> TreeDiff<T> {
+        let side = (samples as f64).sqrt().floor() as usize;
+        let area = union_rect(self.bbox, other.bbox);
+        let mut changes = HashMap::new();
+        let (mut ctx, mut other_ctx) = (QueryContext::new(), QueryContext::new());
+        grid_centers(&area, side, side).iter().for_each(|point| {
+            let before = self.label_with_ctx(point, &mut ctx);
+            let after = other.label_with_ctx(point, &mut other_ctx);
+            if before != after {
+                *changes.entry((before, after)).or_insert(0) += 1;
+            }
+        });
+        TreeDiff {
+            samples: side * side,
+            changes,
+        }
+    }
+
+    /// Returns the label of the region containing the given point or, if no region contains it, the
+    /// label of the nearest region within `max_distance`.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `max_distance` - The maximum distance to a region, in the units of `metric`.
+    /// * `metric` - The metric used to measure distances.
+    ///
+    /// Equidistant regions are broken by label order, see [`LabeledPartitionTree::k_nearest`].
+    pub fn label_or_nearest(
+        &self,
+        point: &Point,
+        max_distance: f64,
+        metric: DistanceMetric,
+    ) -> Option<T>
+    where
+        T: Ord,
+    {
+        self.label(point).or_else(|| {
+            self.nearest_labels(point, 1, max_distance, metric)
+                .into_iter()
+                .next()
+                .map(|(label, _)| label.clone())
+        })
+    }
+
+    /// Returns the `k` labels whose regions are nearest to the given point, along with their
+    /// distances, ordered by ascending distance.
+    ///
+    /// Regions at exactly the same distance are ordered by their labels, so results are
+    /// reproducible across runs even though leaves store their regions in hash maps. Only leaves
+    /// near the point are searched, widening the search until `k` regions are found.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `k` - The maximum number of labels to return.
+    /// * `metric` - The metric used to measure distances.
+    pub fn k_nearest(&self, point: &Point, k: usize, metric: DistanceMetric) -> Vec<(T, f64)>
+    where
+        T: Ord,
+    {
+        self.nearest_labels(point, k, f64::INFINITY, metric)
+            .into_iter()
+            .map(|(label, distance)| (label.clone(), distance))
+            .collect()
+    }
 
-/// A struct representing a labeled partition tree.
-///
-/// This structure is used for performing fast point-in-polygon queries by recursively checking 
-/// bounding boxes before performing the final point-in-polygon check.
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct LabeledPartitionTree<T: Eq + Hash> {
-    children: Box<Vec<LabeledPartitionTree<T>>>,
-    polygons: HashMap<T, MultiPolygon>,
-    bbox: Rect,
-}
+    /// Returns up to `k` labels whose regions are within `max_distance` of the given point, along
+    /// with their distances, ordered by ascending distance and then by label.
+    ///
+    /// Leaves are searched in windows around the point that double in size until they hold `k`
+    /// regions within the window's radius, reach `max_distance`, or cover the whole tree. Any
+    /// region within the radius has a piece in a leaf inside the window, so the distances found
+    /// for those regions are exact.
+    fn nearest_labels(
+        &self,
+        point: &Point,
+        k: usize,
+        max_distance: f64,
+        metric: DistanceMetric,
+    ) -> Vec<(&T, f64)>
+    where
+        T: Ord,
+    {
+        let mut radius = match metric {
+            DistanceMetric::Euclidean => 1.0,
+            DistanceMetric::Haversine => 100.0,
+        };
+        loop {
+            radius = radius.min(max_distance);
+            let mut window = search_window(point, radius, metric);
+            // windows are clipped to the world for great-circle distances, so those are exhaustive
+            // once they span half of the earth's circumference
+            let exhaustive = !radius.is_finite()
+                || rect_contains_rect(&window, &self.bbox)
+                || (metric == DistanceMetric::Haversine && radius >= 20_040.0);
+            if exhaustive {
+                window = self.bbox;
+            }
 
-impl<T: Clone + Eq + Hash> LabeledPartitionTree<T> {
-    /// Constructs a labeled partition tree from a set of labeled polygons.
+            let mut distances: HashMap<&T, f64> = HashMap::new();
+            self.leaves_intersecting(&window).iter().for_each(|leaf| {
+                leaf.polygons.iter().for_each(|(label, polygon)| {
+                    let distance = metric.distance(point, polygon);
+                    let best = distances.entry(label).or_insert(f64::INFINITY);
+                    if distance < *best {
+                        *best = distance;
+                    }
+                })
+            });
+
+            let limit = if exhaustive { max_distance } else { radius };
+            let mut distances: Vec<(&T, f64)> = distances
+                .into_iter()
+                .filter(|(_, distance)| *distance <= limit)
+                .collect();
+            if exhaustive || radius >= max_distance || distances.len() >= k {
+                distances.sort_by(|(a_label, a), (b_label, b)| {
+                    a.partial_cmp(b)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                        .then_with(|| a_label.cmp(b_label))
+                });
+                distances.truncate(k);
+                return distances;
+            }
+            radius *= 2.0;
+        }
+    }
+
+    /// Returns the labels of all regions within `tolerance` of the given point, ordered by
+    /// ascending distance, so that the region containing the point (if any) comes first.
+    ///
+    /// Unlike `label`, this surfaces ambiguity near borders: a point just inside one region but
+    /// within `tolerance` of its neighbor gets both labels. Distances are planar, in degrees of
+    /// longitude/latitude, and regions at exactly the same distance are ordered by their labels.
     ///
     /// # Arguments
-    /// * `selected` - The labels of the polygons to be included in the tree.
-    /// * `polygons` - A map of labels to their corresponding polygons.
-    /// * `bbox` - The bounding box for the current partition.
-    /// * `max_depth` - The maximum depth of the tree. Deeper trees tend to result in faster queries, 
-    ///                 but take much longer to construct.
-    /// * `depth` - The current depth during recursion.
-    pub fn from_labeled_polygons(
-        selected: &Vec<T>,
-        polygons: &HashMap<T, MultiPolygon>,
-        bbox: Rect,
-        max_depth: usize,
+    /// * `point` - The point to check.
+    /// * `tolerance` - The maximum distance to a region, in degrees.
+    pub fn label_near(&self, point: impl QueryPoint, tolerance: f64) -> Vec<T>
+    where
+        T: Ord,
+    {
+        let point = &point.to_point();
+        let window = search_window(point, tolerance, DistanceMetric::Euclidean);
+
+        let mut distances: HashMap<&T, f64> = HashMap::new();
+        self.leaves_intersecting(&window).iter().for_each(|leaf| {
+            leaf.polygons.iter().for_each(|(label, polygon)| {
+                let distance = DistanceMetric::Euclidean.distance(point, polygon);
+                let best = distances.entry(label).or_insert(f64::INFINITY);
+                if distance < *best {
+                    *best = distance;
+                }
+            })
+        });
+
+        let mut distances: Vec<(&T, f64)> = distances
+            .into_iter()
+            .filter(|(_, distance)| *distance <= tolerance)
+            .collect();
+        distances.sort_by(|(a_label, a), (b_label, b)| {
+            a.partial_cmp(b)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a_label.cmp(b_label))
+        });
+        distances.into_iter().map(|(label, _)| label.clone()).collect()
+    }
+
+    /// Returns the labels of all regions within `radius_km` kilometers of the given point
+    /// (great-circle distance), ordered by ascending distance.
+    ///
+    /// Only leaves intersecting the circle's bounding box are searched. Regions at exactly the same
+    /// distance are ordered by their labels.
+    ///
+    /// # Arguments
+    /// * `center` - The point to search around.
+    /// * `radius_km` - The search radius, in kilometers.
+    pub fn labels_within_radius(&self, center: &Point, radius_km: f64) -> Vec<T>
+    where
+        T: Ord,
+    {
+        let window = search_window(center, radius_km, DistanceMetric::Haversine);
+
+        let mut distances: HashMap<&T, f64> = HashMap::new();
+        self.leaves_intersecting(&window).iter().for_each(|leaf| {
+            leaf.polygons.iter().for_each(|(label, polygon)| {
+                let distance = DistanceMetric::Haversine.distance(center, polygon);
+                let best = distances.entry(label).or_insert(f64::INFINITY);
+                if distance < *best {
+                    *best = distance;
+                }
+            })
+        });
+
+        let mut distances: Vec<(&T, f64)> = distances
+            .into_iter()
+            .filter(|(_, distance)| *distance <= radius_km)
+            .collect();
+        distances.sort_by(|(a_label, a), (b_label, b)| {
+            a.partial_cmp(b)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a_label.cmp(b_label))
+        });
+        distances.into_iter().map(|(label, _)| label.clone()).collect()
+    }
+
+    /// Returns every label whose region overlaps the given view, e.g. to list the regions visible
+    /// in a map viewport.
+    ///
+    /// # Arguments
+    /// * `view` - The rectangle to summarize.
+    pub fn labels_in_view(&self, view: Rect) -> HashSet<T> {
+        self.leaves_intersecting(&view)
+            .iter()
+            .flat_map(|leaf| leaf.polygons.iter())
+            .filter(|(_, polygon)| view.intersects(&***polygon))
+            .map(|(label, _)| label.clone())
+            .collect()
+    }
+
+    /// Returns the deepest node of the tree whose cell fully contains the given rectangle, e.g. to
+    /// find the regions that could appear in a map tile with `labels`.
+    ///
+    /// The search descends while a single child's cell still contains the rectangle and stops
+    /// when the rectangle straddles several children. The root is returned if the rectangle isn't
+    /// inside the root's cell.
+    ///
+    /// # Arguments
+    /// * `rect` - The rectangle to enclose.
+    pub fn smallest_enclosing_cell(&self, rect: Rect) -> &LabeledPartitionTree<T> {
+        let mut node = self;
+        while let Some(child) = node
+            .children
+            .iter()
+            .find(|child| rect_contains_rect(&child.bbox, &rect))
+        {
+            node = child;
+        }
+        node
+    }
+
+    /// Returns the bounding box of this node's cell.
+    pub fn bbox(&self) -> Rect {
+        self.bbox
+    }
+
+    /// Returns every label stored in this node or its descendants.
+    pub fn labels(&self) -> HashSet<T> {
+        self.leaves()
+            .iter()
+            .flat_map(|leaf| leaf.polygons.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the leaves of the tree whose cells intersect the given window.
+    fn leaves_intersecting(&self, window: &Rect) -> Vec<&LabeledPartitionTree<T>> {
+        if !self.bbox.intersects(window) {
+            vec![]
+        } else if self.children.is_empty() {
+            vec![self]
+        } else {
+            self.children
+                .iter()
+                .flat_map(|child| child.leaves_intersecting(window))
+                .collect()
+        }
+    }
+
+    /// Returns summary statistics describing the shape of the tree.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        let mut aspect_ratio_sum = 0.0;
+        let mut ground_aspect_ratio_sum = 0.0;
+        let mut aspect_ratio_count = 0;
+        self.collect_stats(
+            0,
+            &mut stats,
+            &mut aspect_ratio_sum,
+            &mut ground_aspect_ratio_sum,
+            &mut aspect_ratio_count,
+        );
+        if aspect_ratio_count > 0 {
+            stats.mean_leaf_aspect_ratio = aspect_ratio_sum / aspect_ratio_count as f64;
+            stats.mean_leaf_ground_aspect_ratio =
+                ground_aspect_ratio_sum / aspect_ratio_count as f64;
+        }
+        stats
+    }
+
+    /// Accumulates the statistics of this node and its descendants.
+    fn collect_stats(
+        &self,
         depth: usize,
-    ) -> LabeledPartitionTree<T> {
-        let (children, inner_polygons) = if depth == max_depth {
-            (
-                Box::new(vec![]),
-                selected
-                    .iter()
-                    .map(|label| {
-                        (
-                            label.clone(),
-                            polygons
-                                .get(label)
-                                .unwrap()
-                                .intersection(&MultiPolygon::from(bbox)), // TODO this intersection is slow
-                        )
-                    })
-                    .collect(),
-            )
-        } else if selected.len() == 0 {
-            (Box::new(vec![]), HashMap::new())
-        } else if selected.len() == 1 && polygons.get(&selected[0]).unwrap().contains(&bbox) {
-            // TODO the check for this is slow
-            (
-                Box::new(vec![]),
-                vec![(selected[0].clone(), MultiPolygon::from(bbox))]
-                    .into_iter()
-                    .collect(),
-            )
+        stats: &mut TreeStats,
+        aspect_ratio_sum: &mut f64,
+        ground_aspect_ratio_sum: &mut f64,
+        aspect_ratio_count: &mut usize,
+    ) {
+        stats.nodes += 1;
+        if self.children.is_empty() {
+            stats.leaves += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            let (width, height) = (self.bbox.width(), self.bbox.height());
+            let ground_width = width * self.bbox.center().y.to_radians().cos().abs();
+            if ground_width > 0.0 && height > 0.0 {
+                *aspect_ratio_sum += width.max(height) / width.min(height);
+                *ground_aspect_ratio_sum += ground_width.max(height) / ground_width.min(height);
+                *aspect_ratio_count += 1;
+            }
         } else {
-            // TODO check if a different branching factor can speed things up
-            let [ab, cd] = bbox.split_x();
-            let [a, b] = ab.split_y();
-            let [c, d] = cd.split_y();
-            let bboxes = vec![a, b, c, d];
+            self.children.iter().for_each(|child| {
+                child.collect_stats(
+                    depth + 1,
+                    stats,
+                    aspect_ratio_sum,
+                    ground_aspect_ratio_sum,
+                    aspect_ratio_count,
+                )
+            });
+        }
+    }
 
-            let bbox_selected_polygons: Vec<Vec<T>> = bboxes
+    /// Converts the tree into a compact form for serialization, which omits the bounding boxes of
+    /// child cells that can be recomputed by splitting their parent's cell with `split`.
+    ///
+    /// `split` should be the rule the tree was built with. Cells that don't follow it (e.g. the
+    /// children of a merged tree) keep their bounding boxes, so the conversion is lossless either
+    /// way; only the savings depend on it.
+    ///
+    /// # Arguments
+    /// * `split` - The rule used to split cells when the tree was built.
+    pub fn to_compact(&self, split: SplitStrategy) -> CompactTree<T> {
+        CompactTree {
+            split,
+            bbox: self.bbox,
+            altitudes: self.altitudes.clone(),
+            root: self.compact_node(split, Some(self.bbox)),
+        }
+    }
+
+    /// Converts this node into a compact node, omitting its bounding box if it is `expected`.
+    fn compact_node(&self, split: SplitStrategy, expected: Option<Rect>) -> CompactNode<T> {
+        let expected_children = if self.children.is_empty() {
+            vec![]
+        } else {
+            split.split(self.bbox)
+        };
+        let follows_split = expected_children.len() == self.children.len();
+        CompactNode {
+            bbox: if expected == Some(self.bbox) {
+                None
+            } else {
+                Some(self.bbox)
+            },
+            children: self
+                .children
                 .iter()
-                .map(|bbox| {
-                    // TODO it might be possible to speed up this intersection check
-                    selected
-                        .iter()
-                        .filter(|&label| bbox.intersects(polygons.get(label).unwrap()))
-                        .cloned()
-                        .collect()
+                .enumerate()
+                .map(|(i, child)| {
+                    let expected = if follows_split {
+                        Some(expected_children[i])
+                    } else {
+                        None
+                    };
+                    child.compact_node(split, expected)
                 })
-                .collect();
+                .collect(),
+            polygons: self.polygons.clone(),
+            overlapping: self.overlapping,
+        }
+    }
 
-            (
-                Box::new(
-                    bbox_selected_polygons
-                        .iter()
-                        .zip(bboxes)
-                        .map(|(selected, bbox)| {
-                            LabeledPartitionTree::from_labeled_polygons(
-                                selected,
-                                polygons,
-                                bbox,
-                                max_depth,
-                                depth + 1,
-                            )
-                        })
-                        .collect(),
-                ),
-                HashMap::new(),
-            )
-        };
+    /// Reconstructs a tree from its compact form, recomputing the omitted bounding boxes.
+    ///
+    /// # Arguments
+    /// * `compact` - The compact form of the tree, e.g. from `to_compact`.
+    pub fn from_compact(compact: CompactTree<T>) -> Self {
+        let mut tree = LabeledPartitionTree::expand_node(compact.root, compact.bbox, compact.split);
+        tree.altitudes = compact.altitudes;
+        tree
+    }
 
+    /// Reconstructs a node from a compact node whose bounding box is `bbox` unless it was stored.
+    fn expand_node(node: CompactNode<T>, bbox: Rect, split: SplitStrategy) -> Self {
+        let bbox = node.bbox.unwrap_or(bbox);
+        let expected_children = if node.children.is_empty() {
+            vec![]
+        } else {
+            split.split(bbox)
+        };
         LabeledPartitionTree {
-            children,
+            children: Box::new(
+                node.children
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, child)| {
+                        // children that don't follow the split rule always store their bboxes
+                        let expected = expected_children.get(i).copied().unwrap_or(bbox);
+                        LabeledPartitionTree::expand_node(child, expected, split)
+                    })
+                    .collect(),
+            ),
+            polygons: node.polygons,
             bbox,
-            polygons: inner_polygons,
+            overlapping: node.overlapping,
+            altitudes: HashMap::new(),
         }
     }
 
-    /// Returns the label of the partition that contains the given point.
+    /// Compares the tree's labels with the brute-force labels of the reference polygons on
+    /// uniformly random points in the tree's bounding box.
+    ///
+    /// This quantifies the accuracy cost of options that trade accuracy for speed, like building
+    /// from simplified polygons. The tree agrees with the reference on a point if neither labels
+    /// it, or if the tree's label's reference polygon contains it, so overlapping regions don't
+    /// count as disagreements.
     ///
-    /// This method recursively searches for the leaf node that contains the point and returns its label.
-    /// If no leaf node contains the point, `None` is returned.
-    /// 
     /// # Arguments
-    /// * `point` - The point to check.
-    pub fn label(&self, point: &Point) -> Option<T> {
-        if self.children.is_empty() {
-            self.polygons.iter().find_map(|(label, polygon)| {
-                if polygon.contains(point) {
-                    Some(label.clone())
+    /// * `reference` - The polygons the tree should represent, e.g. the unsimplified originals.
+    /// * `samples` - The number of points to sample.
+    /// * `rng` - The random number generator used to sample points.
+    #[cfg(feature = "testing")]
+    pub fn accuracy_report(
+        &self,
+        reference: &HashMap<T, MultiPolygon>,
+        samples: usize,
+        rng: &mut impl Rng,
+    ) -> AccuracyReport {
+        let disagreements = (0..samples)
+            .map(|_| {
+                Point::new(
+                    rng.gen_range(self.bbox.min().x..=self.bbox.max().x),
+                    rng.gen_range(self.bbox.min().y..=self.bbox.max().y),
+                )
+            })
+            .filter(|point| match self.label_ref(point) {
+                Some(label) => !reference
+                    .get(label)
+                    .map_or(false, |polygon| polygon.contains(point)),
+                None => brute_force_label(reference, point).is_some(),
+            })
+            .collect();
+        AccuracyReport {
+            samples,
+            disagreements,
+        }
+    }
+
+    /// Rounds every polygon coordinate in the tree to the given number of decimal places, e.g. to
+    /// shrink serialized trees. Five decimal places of a degree are about a meter.
+    ///
+    /// This is lossy: points within the rounding error of a border may change labels. Cell
+    /// bounding boxes are not rounded, so the structure of the tree is unchanged.
+    ///
+    /// # Arguments
+    /// * `decimals` - The number of decimal places to keep.
+    pub fn round_coordinates(&mut self, decimals: u32) {
+        let scale = 10f64.powi(decimals as i32);
+        let round = |coord: &mut Coord| {
+            coord.x = (coord.x * scale).round() / scale;
+            coord.y = (coord.y * scale).round() / scale;
+        };
+        // polygons shared between the leaves of unclipped trees are rounded once, so the leaves
+        // keep sharing them
+        let mut rounded: HashMap<*const MultiPolygon, Arc<MultiPolygon>> = HashMap::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.polygons
+                .values_mut()
+                .for_each(|polygon| match polygon {
+                    LeafPolygon::Owned(polygon) => map_coords_mut(polygon, round),
+                    LeafPolygon::Shared(polygon) => {
+                        let shared = rounded.entry(Arc::as_ptr(polygon)).or_insert_with(|| {
+                            let mut copy = MultiPolygon::clone(polygon);
+                            map_coords_mut(&mut copy, round);
+                            Arc::new(copy)
+                        });
+                        *polygon = Arc::clone(shared);
+                    }
+                });
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Releases the memory that the tree's vectors and maps have allocated but aren't using, e.g.
+    /// before holding a tree in memory for a long time.
+    ///
+    /// Each leaf's map is shrunk to the smallest capacity that fits its entries, which for the
+    /// common single-label leaves is the smallest map that can be allocated. The polygon of a
+    /// single-label leaf that covers the leaf's whole cell is replaced by the cell itself, as
+    /// builds do for cells inside a region, e.g. for leaves inside large regions of trees built
+    /// without clipping.
+    pub fn compact(&mut self) {
+        self.altitudes.shrink_to_fit();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.children.is_empty() && node.polygons.len() == 1 {
+                if let Some(polygon) = node.polygons.values_mut().next() {
+                    // the cell's own polygon has 5 coordinates, so smaller polygons are kept
+                    if polygon.coords_count() > 5 && polygon.contains(&node.bbox) {
+                        *polygon = LeafPolygon::Owned(node.bbox.into());
+                    }
+                }
+            }
+            node.polygons.shrink_to_fit();
+            // polygons shared between the leaves of unclipped trees are left as they are, since
+            // shrinking them would copy them into every leaf
+            node.polygons
+                .values_mut()
+                .filter_map(|polygon| match polygon {
+                    LeafPolygon::Owned(polygon) => Some(polygon),
+                    LeafPolygon::Shared(_) => None,
+                })
+                .for_each(|polygon| {
+                    polygon.0.shrink_to_fit();
+                    polygon.0.iter_mut().for_each(|part| {
+                        part.exterior_mut(|exterior| exterior.0.shrink_to_fit());
+                        part.interiors_mut(|interiors| {
+                            interiors
+                                .iter_mut()
+                                .for_each(|interior| interior.0.shrink_to_fit())
+                        });
+                    })
+                });
+            node.children.shrink_to_fit();
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Returns a random point inside the region with the given label, or `None` if the tree has
+    /// no region with the label or no point inside it was found after many attempts.
+    ///
+    /// A leaf containing part of the region is picked with probability proportional to the area
+    /// of that part, and a point is rejection-sampled from the part's bounding box, so points are
+    /// uniform in longitude/latitude over the region. For trees built without clipping, parts are
+    /// whole polygons that can span several leaves, so the distribution is only approximate.
+    ///
+    /// # Arguments
+    /// * `label` - The label of the region to sample from.
+    /// * `rng` - The random number generator used to sample the point.
+    pub fn sample_point_in_label(&self, label: &T, rng: &mut impl Rng) -> Option<Point> {
+        let parts: Vec<(Rect, &MultiPolygon, f64)> = self
+            .leaves()
+            .into_iter()
+            .filter_map(|leaf| {
+                let polygon: &MultiPolygon = leaf.polygons.get(label)?;
+                let part_bbox = polygon.bounding_rect()?;
+                let min = (
+                    part_bbox.min().x.max(leaf.bbox.min().x),
+                    part_bbox.min().y.max(leaf.bbox.min().y),
+                );
+                let max = (
+                    part_bbox.max().x.min(leaf.bbox.max().x),
+                    part_bbox.max().y.min(leaf.bbox.max().y),
+                );
+                let area = polygon.unsigned_area();
+                if min.0 <= max.0 && min.1 <= max.1 && area > 0.0 {
+                    Some((Rect::new(min, max), polygon, area))
                 } else {
                     None
                 }
             })
-        } else {
-            self.children
+            .collect();
+        if parts.is_empty() {
+            return None;
+        }
+        let total_area: f64 = parts.iter().map(|(_, _, area)| area).sum();
+
+        let mut target = rng.gen_range(0.0..total_area);
+        let (sample_bbox, polygon) = parts
+            .iter()
+            .find(|(_, _, area)| {
+                target -= area;
+                target < 0.0
+            })
+            .or(parts.last())
+            .map(|(sample_bbox, polygon, _)| (sample_bbox, polygon))?;
+
+        // give up rather than loop forever on parts too thin to hit
+        (0..SAMPLE_ATTEMPTS).find_map(|_| {
+            let point = Point::new(
+                rng.gen_range(sample_bbox.min().x..=sample_bbox.max().x),
+                rng.gen_range(sample_bbox.min().y..=sample_bbox.max().y),
+            );
+            if polygon.contains(&point) {
+                Some(point)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the total number of polygon vertices stored in the tree's leaves, which correlates
+    /// with its memory use and query cost.
+    ///
+    /// Clipping regions to leaf cells adds vertices along cell boundaries, so this is usually more
+    /// than the number of vertices in the source polygons.
+    pub fn total_vertices(&self) -> usize {
+        self.leaves()
+            .iter()
+            .flat_map(|leaf| leaf.polygons.values())
+            .map(|polygon| polygon.coords_count())
+            .sum()
+    }
+
+    /// Checks that the tree is internally consistent, e.g. after loading it from a cache, and
+    /// returns every broken invariant found.
+    ///
+    /// Every child must lie inside its parent, nodes with children must not store polygons, and
+    /// the children of each node must cover it without gaps or overlaps. The root of a tree from
+    /// [`LabeledPartitionTree::merge`] covers the gaps between the merged trees, so it does not
+    /// pass the last check.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        let mut stack: Vec<(&LabeledPartitionTree<T>, Vec<usize>)> = vec![(self, vec![])];
+        while let Some((node, path)) = stack.pop() {
+            if node.children.is_empty() {
+                continue;
+            }
+            if !node.polygons.is_empty() {
+                errors.push(ValidationError::InternalNodeWithPolygons { path: path.clone() });
+            }
+
+            // children tile their parent if they lie inside it, don't overlap, and have the same
+            // total area; the areas are compared with a tolerance for rounding errors
+            let area = node.bbox.width() * node.bbox.height();
+            let children_area: f64 = node
+                .children
                 .iter()
-                .filter(|child| child.bbox.contains(point))
-                .find_map(|child| child.label(point))
+                .map(|child| child.bbox.width() * child.bbox.height())
+                .sum();
+            let overlap = node.children.iter().enumerate().any(|(i, a)| {
+                node.children[i + 1..].iter().any(|b| {
+                    let width =
+                        a.bbox.max().x.min(b.bbox.max().x) - a.bbox.min().x.max(b.bbox.min().x);
+                    let height =
+                        a.bbox.max().y.min(b.bbox.max().y) - a.bbox.min().y.max(b.bbox.min().y);
+                    width > 0.0 && height > 0.0
+                })
+            });
+            if overlap || (area - children_area).abs() > area * 1e-9 {
+                errors.push(ValidationError::ChildrenDoNotTile { path: path.clone() });
+            }
+
+            for (i, child) in node.children.iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                if !rect_contains_rect(&node.bbox, &child.bbox) {
+                    errors.push(ValidationError::ChildOutsideParent {
+                        path: child_path.clone(),
+                    });
+                }
+                stack.push((child, child_path));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
+    /// Returns the number of leaves holding more than `threshold` polygons. Queries in these leaves
+    /// are slow, so a tree with many of them would probably benefit from a larger maximum depth.
+    ///
+    /// # Arguments
+    /// * `threshold` - The largest number of polygons a leaf can hold without being overloaded.
+    pub fn overloaded_leaves(&self, threshold: usize) -> usize {
+        self.leaves()
+            .iter()
+            .filter(|leaf| leaf.polygons.len() > threshold)
+            .count()
+    }
+
+    /// Returns the bounding box of all of the polygons stored in the tree, or `None` if the tree
+    /// labels nothing.
+    ///
+    /// Unlike the root's bounding box, which usually covers the whole world, this is the area
+    /// the tree's data actually covers.
+    pub fn extent(&self) -> Option<Rect> {
+        self.leaves()
+            .iter()
+            .flat_map(|leaf| leaf.polygons.values())
+            .filter_map(|polygon| polygon.bounding_rect())
+            .reduce(union_rect)
+    }
+
     /// Returns the number of leaf nodes in the tree.
     pub fn size(&self) -> usize {
         if self.children.is_empty() {
@@ -139,31 +2530,142 @@ impl<T: Clone + Eq + Hash> LabeledPartitionTree<T> {
         }
     }
 
+    /// Returns the total number of nodes in the tree, including internal nodes and leaves.
+    pub fn node_count(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|child| child.node_count())
+            .sum::<usize>()
+    }
+
+    /// Returns an estimate of the number of bytes the tree occupies in memory, including its nodes,
+    /// child vectors, label maps, and stored polygons, e.g. to choose a `max_depth` that fits a
+    /// memory budget.
+    ///
+    /// Labels are counted by their inline size only, so heap data owned by labels (e.g. the
+    /// characters of `String` labels) is not included, nor is allocator overhead. Polygons shared
+    /// between leaves are split evenly between them.
+    pub fn estimated_bytes(&self) -> usize {
+        size_of::<Self>() + self.heap_bytes()
+    }
+
+    /// Returns an estimate of the number of heap bytes owned by this node and its descendants.
+    fn heap_bytes(&self) -> usize {
+        let children = size_of::<Vec<Self>>()
+            + self.children.capacity() * size_of::<Self>()
+            + self
+                .children
+                .iter()
+                .map(|child| child.heap_bytes())
+                .sum::<usize>();
+        let map_entry = size_of::<T>() + size_of::<LeafPolygon>();
+        let polygons = self.polygons.capacity() * map_entry
+            + self
+                .polygons
+                .values()
+                .map(|polygon| match polygon {
+                    LeafPolygon::Owned(polygon) => multi_polygon_heap_bytes(polygon),
+                    LeafPolygon::Shared(polygon) => {
+                        // the allocation also holds the strong and weak reference counts
+                        let bytes = 2 * size_of::<usize>()
+                            + size_of::<MultiPolygon>()
+                            + multi_polygon_heap_bytes(polygon);
+                        bytes / Arc::strong_count(polygon)
+                    }
+                })
+                .sum::<usize>();
+        let altitudes = self.altitudes.capacity() * (size_of::<T>() + size_of::<(f64, f64)>());
+        children + polygons + altitudes
+    }
+
     /// Plots the labeled partition tree and saves the image to the specified path.
     ///
     /// # Arguments
     /// * `out_path` - The path where the resulting image will be saved.
+    #[cfg(feature = "plotting")]
     pub fn plot(&self, out_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
         let root = BitMapBackend::new(out_path, (4000, 3000)).into_drawing_area();
         root.fill(&WHITE)?;
-        let mut chart = ChartBuilder::on(&root)
+        self.draw_into(&root)?;
+        root.present()?;
+        Ok(())
+    }
+
+    /// Plots the labeled partition tree over the given coordinate ranges and saves the image to
+    /// the specified path, e.g. to zoom into a regional tree.
+    ///
+    /// # Arguments
+    /// * `out_path` - The path where the resulting image will be saved.
+    /// * `x_range` - The range of longitudes to plot, defaulting to that of the tree's extent.
+    /// * `y_range` - The range of latitudes to plot, defaulting to that of the tree's extent.
+    #[cfg(feature = "plotting")]
+    pub fn plot_range(
+        &self,
+        out_path: &Path,
+        x_range: Option<Range<f64>>,
+        y_range: Option<Range<f64>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let extent = self.extent().unwrap_or(self.bbox);
+        let x_range = x_range.unwrap_or(extent.min().x..extent.max().x);
+        let y_range = y_range.unwrap_or(extent.min().y..extent.max().y);
+
+        let root = BitMapBackend::new(out_path, (4000, 3000)).into_drawing_area();
+        root.fill(&WHITE)?;
+        self.draw_range_into(&root, x_range, y_range)?;
+        root.present()?;
+        Ok(())
+    }
+
+    /// Draws the cells of the labeled partition tree into the given drawing area, e.g. to compose
+    /// the plot with other overlays or render it at a custom size.
+    ///
+    /// The area is not filled or presented; that is left to the caller.
+    ///
+    /// # Arguments
+    /// * `area` - The drawing area to draw into.
+    #[cfg(feature = "plotting")]
+    pub fn draw_into<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        self.draw_range_into(area, -180.0..180.0, -90.0..90.0)
+    }
+
+    /// Draws the cells of the labeled partition tree into the given drawing area over the given
+    /// coordinate ranges.
+    #[cfg(feature = "plotting")]
+    fn draw_range_into<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        x_range: Range<f64>,
+        y_range: Range<f64>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let mut chart = ChartBuilder::on(area)
             .margin(5)
             .x_label_area_size(30)
             .y_label_area_size(30)
-            .build_cartesian_2d(-180f32..180f32, -90f32..90f32)?;
+            .build_cartesian_2d(
+                x_range.start as f32..x_range.end as f32,
+                y_range.start as f32..y_range.end as f32,
+            )?;
 
         chart.configure_mesh().draw()?;
 
         let bboxes = self.bboxes();
-        bboxes.iter().for_each(|bbox| {
-            chart
-                .draw_series(LineSeries::new(
-                    bbox.coords_iter()
-                        .map(|coord| (coord.x as f32, coord.y as f32)),
-                    &RED,
-                ))
-                .unwrap();
-        });
+        for bbox in bboxes.iter() {
+            chart.draw_series(LineSeries::new(
+                bbox.coords_iter()
+                    .map(|coord| (coord.x as f32, coord.y as f32)),
+                &RED,
+            ))?;
+        }
 
         chart
             .configure_series_labels()
@@ -171,12 +2673,14 @@ impl<T: Clone + Eq + Hash> LabeledPartitionTree<T> {
             .border_style(&BLACK)
             .draw()?;
 
-        root.present()?;
         Ok(())
     }
 
     /// Returns a vector of bounding boxes for all leaf nodes in the labeled partition tree.
-    fn bboxes(&self) -> Vec<Rect> {
+    ///
+    /// Leaves are listed depth-first, visiting children in the order they were split, so the
+    /// order is the same every time for a given tree (e.g. for reproducible plots).
+    pub fn bboxes(&self) -> Vec<Rect> {
         if self.children.is_empty() {
             vec![self.bbox]
         } else {
@@ -187,5 +2691,106 @@ impl<T: Clone + Eq + Hash> LabeledPartitionTree<T> {
                 .collect()
         }
     }
+
+    /// Returns the cells of the leaves holding each label, e.g. to export a grid-based lookup table
+    /// or visualize each region's coverage. Each label's cells are in the same order as
+    /// [`LabeledPartitionTree::bboxes`].
+    pub fn cells_by_label(&self) -> HashMap<T, Vec<Rect>> {
+        let mut cells: HashMap<T, Vec<Rect>> = HashMap::new();
+        for leaf in self.leaves() {
+            for label in leaf.polygons.keys() {
+                cells.entry(label.clone()).or_insert(Vec::new()).push(leaf.bbox);
+            }
+        }
+        cells
+    }
+
+    /// Returns the bounding box of every leaf node along with its sorted labels, in the same
+    /// order as [`LabeledPartitionTree::bboxes`].
+    pub fn labeled_bboxes(&self) -> Vec<(Rect, Vec<T>)>
+    where
+        T: Ord,
+    {
+        self.leaves()
+            .into_iter()
+            .map(|leaf| {
+                let mut labels: Vec<T> = leaf.polygons.keys().cloned().collect();
+                labels.sort();
+                (leaf.bbox, labels)
+            })
+            .collect()
+    }
+
+    /// Returns all leaf nodes in the labeled partition tree.
+    fn leaves(&self) -> Vec<&LabeledPartitionTree<T>> {
+        if self.children.is_empty() {
+            vec![self]
+        } else {
+            self.children
+                .iter()
+                .map(|child| child.leaves())
+                .flatten()
+                .collect()
+        }
+    }
 }
 
+impl<T: Clone + Eq + Hash> LabeledPartitionTree<(T, usize)> {
+    /// Returns the label of the partition that contains the given point along with the index of
+    /// the source feature it fell in, for trees built from polygons keyed by label and feature
+    /// index, e.g. from `datasets::labeled_feature_polygons`.
+    ///
+    /// Unlike `label_with_source`, this identifies the exact feature that matched when a label
+    /// spans many features (e.g. islands or enclaves).
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn label_with_feature_index(&self, point: impl QueryPoint) -> Option<(T, usize)> {
+        self.label(point)
+    }
+}
+
+impl LabeledPartitionTree<String> {
+    /// Converts the tree's labels into shared `Arc<str>`s, so that every cell with the same label
+    /// shares one allocation and `label` returns a cheap reference-counted clone rather than a
+    /// freshly allocated `String`, e.g. for bulk queries that resolve to a few labels.
+    pub fn into_interned(self) -> LabeledPartitionTree<Arc<str>> {
+        let mut interned = HashMap::new();
+        self.intern_node(&mut interned)
+    }
+
+    /// Converts this node's labels using the given map of labels to their shared copies.
+    fn intern_node(
+        self,
+        interned: &mut HashMap<String, Arc<str>>,
+    ) -> LabeledPartitionTree<Arc<str>> {
+        let mut intern = |label: String| -> Arc<str> {
+            interned
+                .entry(label)
+                .or_insert_with_key(|label| Arc::from(label.as_str()))
+                .clone()
+        };
+        let polygons = self
+            .polygons
+            .into_iter()
+            .map(|(label, polygon)| (intern(label), polygon))
+            .collect();
+        let altitudes = self
+            .altitudes
+            .into_iter()
+            .map(|(label, band)| (intern(label), band))
+            .collect();
+        LabeledPartitionTree {
+            children: Box::new(
+                (*self.children)
+                    .into_iter()
+                    .map(|child| child.intern_node(interned))
+                    .collect(),
+            ),
+            polygons,
+            bbox: self.bbox,
+            overlapping: self.overlapping,
+            altitudes,
+        }
+    }
+}
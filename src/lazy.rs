@@ -0,0 +1,242 @@
+//! Labeled partition trees whose leaf geometry is loaded from disk on first access.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::Hash,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use geo::{Contains, MultiPolygon, Point, Rect};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::labeling::{LabeledPartitionTree, QueryPoint};
+
+/// A node of a tree skeleton, which only stores the structure of the tree.
+#[derive(Serialize, Deserialize)]
+struct SkeletonNode {
+    bbox: Rect,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<SkeletonNode>,
+    /// The id of the leaf's geometry, only set on leaves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leaf: Option<usize>,
+    /// Whether the cells of the node's children may overlap, e.g. at the root of a merged tree.
+    #[serde(default, skip_serializing_if = "is_false")]
+    overlapping: bool,
+}
+
+/// Returns whether the flag is unset, e.g. to skip serializing unset flags.
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// The structure of a tree along with where each leaf's geometry is stored in the geometry file.
+#[derive(Serialize, Deserialize)]
+struct Skeleton {
+    root: SkeletonNode,
+    /// The byte offset and length of each leaf's geometry, indexed by leaf id.
+    leaves: Vec<(u64, u64)>,
+}
+
+/// The geometry of a leaf, which is read from the geometry file the first time it is needed.
+struct LazyLeaf<T> {
+    offset: u64,
+    length: u64,
+    polygons: OnceLock<HashMap<T, MultiPolygon>>,
+}
+
+/// A labeled partition tree that loads the geometry of each leaf on first access.
+///
+/// The tree is stored as two files: a small skeleton with the bounding boxes and structure of the
+/// tree, and a geometry file with the polygons of each leaf. Opening a tree only reads the
+/// skeleton, so processes that only query a few cells (e.g. serverless functions) don't pay to
+/// deserialize the whole tree. Altitude bands are not stored.
+pub struct LazyLabeledPartitionTree<T> {
+    root: SkeletonNode,
+    leaves: Vec<LazyLeaf<T>>,
+    geometry_path: PathBuf,
+}
+
+/// Saves a tree in the two-file format read by `LazyLabeledPartitionTree::open`.
+///
+/// # Arguments
+/// * `tree` - The tree to save.
+/// * `skeleton_path` - The path where the structure of the tree will be saved.
+/// * `geometry_path` - The path where the geometry of the tree's leaves will be saved.
+pub fn save_lazy<T: Eq + Hash + Serialize>(
+    tree: &LabeledPartitionTree<T>,
+    skeleton_path: &Path,
+    geometry_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut geometry = BufWriter::new(File::create(geometry_path)?);
+    let mut leaves = vec![];
+    let root = skeleton_node(tree, &mut geometry, &mut leaves)?;
+    geometry.flush()?;
+
+    let mut writer = BufWriter::new(File::create(skeleton_path)?);
+    serde_json::to_writer(&mut writer, &Skeleton { root, leaves })?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Converts a node into a skeleton node, appending the geometry of its leaves to `geometry` and
+/// their offsets and lengths to `leaves`.
+fn skeleton_node<T: Eq + Hash + Serialize>(
+    node: &LabeledPartitionTree<T>,
+    geometry: &mut impl Write,
+    leaves: &mut Vec<(u64, u64)>,
+) -> Result<SkeletonNode, Box<dyn std::error::Error>> {
+    if node.children.is_empty() {
+        let bytes = serde_json::to_vec(&node.polygons)?;
+        let offset = leaves
+            .last()
+            .map(|(offset, length)| offset + length)
+            .unwrap_or(0);
+        geometry.write_all(&bytes)?;
+        leaves.push((offset, bytes.len() as u64));
+        Ok(SkeletonNode {
+            bbox: node.bbox,
+            children: vec![],
+            leaf: Some(leaves.len() - 1),
+            overlapping: false,
+        })
+    } else {
+        Ok(SkeletonNode {
+            bbox: node.bbox,
+            children: node
+                .children
+                .iter()
+                .map(|child| skeleton_node(child, geometry, leaves))
+                .collect::<Result<_, _>>()?,
+            leaf: None,
+            overlapping: node.overlapping,
+        })
+    }
+}
+
+impl<T: Clone + Eq + Hash + DeserializeOwned> LazyLabeledPartitionTree<T> {
+    /// Opens a tree saved with `save_lazy`, reading only its skeleton.
+    ///
+    /// # Arguments
+    /// * `skeleton_path` - The path of the structure of the tree.
+    /// * `geometry_path` - The path of the geometry of the tree's leaves.
+    pub fn open(
+        skeleton_path: &Path,
+        geometry_path: &Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(skeleton_path)?);
+        let skeleton: Skeleton = serde_json::from_reader(reader)?;
+        Ok(LazyLabeledPartitionTree {
+            root: skeleton.root,
+            leaves: skeleton
+                .leaves
+                .into_iter()
+                .map(|(offset, length)| LazyLeaf {
+                    offset,
+                    length,
+                    polygons: OnceLock::new(),
+                })
+                .collect(),
+            geometry_path: geometry_path.to_path_buf(),
+        })
+    }
+
+    /// Returns the label of the partition that contains the given point, loading the geometry of
+    /// the leaves the query visits if they haven't been loaded yet.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the geometry of a leaf can't be read from the geometry file.
+    pub fn label(&self, point: impl QueryPoint) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        let point = &point.to_point();
+        // same single-path descent as `LabeledPartitionTree::label_ref`
+        let mut node = &self.root;
+        loop {
+            match node.leaf {
+                Some(id) => return self.leaf_label(id, point),
+                None if node.overlapping => return self.label_backtracking(node, point),
+                None => match node
+                    .children
+                    .iter()
+                    .find(|child| child.bbox.contains(point))
+                {
+                    Some(child) => node = child,
+                    None => return Ok(None),
+                },
+            }
+        }
+    }
+
+    /// Returns the label of the partition below `node` that contains the given point, searching
+    /// every child that contains it in order until one labels it, e.g. below a node whose
+    /// children overlap.
+    fn label_backtracking(
+        &self,
+        node: &SkeletonNode,
+        point: &Point,
+    ) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        // children are pushed in reverse so they are visited in order
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            match node.leaf {
+                Some(id) => {
+                    if let Some(label) = self.leaf_label(id, point)? {
+                        return Ok(Some(label));
+                    }
+                }
+                None => stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|child| child.bbox.contains(point)),
+                ),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the label of the polygon in the given leaf that contains the given point, if any.
+    fn leaf_label(
+        &self,
+        id: usize,
+        point: &Point,
+    ) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        Ok(self
+            .leaf_polygons(id)?
+            .iter()
+            .find(|(_, polygon)| polygon.contains(point))
+            .map(|(label, _)| label.clone()))
+    }
+
+    /// Returns the number of leaves whose geometry has been loaded so far.
+    pub fn loaded_leaves(&self) -> usize {
+        self.leaves
+            .iter()
+            .filter(|leaf| leaf.polygons.get().is_some())
+            .count()
+    }
+
+    /// Returns the geometry of the given leaf, reading it from the geometry file if needed.
+    fn leaf_polygons(
+        &self,
+        id: usize,
+    ) -> Result<&HashMap<T, MultiPolygon>, Box<dyn std::error::Error>> {
+        let leaf = &self.leaves[id];
+        if let Some(polygons) = leaf.polygons.get() {
+            return Ok(polygons);
+        }
+        let mut file = File::open(&self.geometry_path)?;
+        file.seek(SeekFrom::Start(leaf.offset))?;
+        let mut bytes = vec![0; leaf.length as usize];
+        file.read_exact(&mut bytes)?;
+        let polygons = serde_json::from_slice(&bytes)?;
+        // if another thread loaded the leaf in the meantime, its copy is kept
+        Ok(leaf.polygons.get_or_init(|| polygons))
+    }
+}
@@ -9,4 +9,11 @@
 //! See the examples folder for full code examples for downloading data, computing the label trees, and finally performing millions of point-in-country/point-in-province lookups.
 
 pub mod datasets;
-pub mod labeling;
\ No newline at end of file
+pub mod fixed;
+pub mod geometry;
+pub mod labelers;
+pub mod labeling;
+pub mod lazy;
+pub mod lines;
+#[cfg(feature = "shared")]
+pub mod shared;
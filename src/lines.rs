@@ -0,0 +1,150 @@
+//! Partition trees over labeled lines, e.g. road centerlines, for nearest-line queries.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use geo::{EuclideanDistance, Intersects, MultiLineString, Rect};
+
+use crate::labeling::{QueryPoint, SplitStrategy};
+
+/// A partition tree over labeled lines that answers "which line is this point nearest to?"
+/// queries, e.g. for matching GPS points to roads.
+///
+/// Like `LabeledPartitionTree`, cells are recursively split into quadrants, and each leaf records
+/// the labels of the lines that cross it. Queries only measure the distance to lines in the leaves
+/// near the query point. The lines themselves are stored once, at the root.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LabeledLinePartitionTree<T: Eq + Hash> {
+    root: LineNode<T>,
+    lines: HashMap<T, MultiLineString>,
+}
+
+/// A node of a `LabeledLinePartitionTree`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct LineNode<T> {
+    children: Vec<LineNode<T>>,
+    labels: Vec<T>,
+    bbox: Rect,
+}
+
+impl<T: Clone + Eq + Hash> LabeledLinePartitionTree<T> {
+    /// Constructs a line partition tree from a set of labeled lines.
+    ///
+    /// # Arguments
+    /// * `lines` - A map of labels to their corresponding lines.
+    /// * `bbox` - The bounding box covered by the root of the tree.
+    /// * `max_depth` - The maximum depth of the tree. Cells crossed by at most one line are not
+    ///                 split any further.
+    pub fn from_labeled_lines(
+        lines: &HashMap<T, MultiLineString>,
+        bbox: Rect,
+        max_depth: usize,
+    ) -> Self {
+        let selected: Vec<T> = lines.keys().cloned().collect();
+        LabeledLinePartitionTree {
+            root: LineNode::build(&selected, lines, bbox, max_depth, 0),
+            lines: lines.clone(),
+        }
+    }
+
+    /// Returns the label of the line nearest to the given point along with its distance, or
+    /// `None` if no line is within `tolerance`.
+    ///
+    /// Distances are planar, in degrees of longitude/latitude. Lines at exactly the same distance
+    /// are broken by label order.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    /// * `tolerance` - The maximum distance to a line, in degrees.
+    pub fn nearest(&self, point: impl QueryPoint, tolerance: f64) -> Option<(T, f64)>
+    where
+        T: Ord,
+    {
+        let point = point.to_point();
+        let window = Rect::new(
+            (point.x() - tolerance, point.y() - tolerance),
+            (point.x() + tolerance, point.y() + tolerance),
+        );
+        let mut candidates: HashSet<&T> = HashSet::new();
+        self.root.collect_labels(&window, &mut candidates);
+
+        candidates
+            .into_iter()
+            .map(|label| (label, point.euclidean_distance(&self.lines[label])))
+            .filter(|(_, distance)| *distance <= tolerance)
+            .min_by(|(a_label, a), (b_label, b)| {
+                a.partial_cmp(b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a_label.cmp(b_label))
+            })
+            .map(|(label, distance)| (label.clone(), distance))
+    }
+
+    /// Returns the number of leaf nodes in the tree.
+    pub fn size(&self) -> usize {
+        self.root.size()
+    }
+}
+
+impl<T: Clone + Eq + Hash> LineNode<T> {
+    /// Recursively builds the node covering `bbox` from the selected lines that cross it.
+    fn build(
+        selected: &Vec<T>,
+        lines: &HashMap<T, MultiLineString>,
+        bbox: Rect,
+        max_depth: usize,
+        depth: usize,
+    ) -> Self {
+        if depth == max_depth || selected.len() <= 1 {
+            return LineNode {
+                children: vec![],
+                labels: selected.clone(),
+                bbox,
+            };
+        }
+
+        let children = SplitStrategy::Quadrants
+            .split(bbox)
+            .into_iter()
+            .map(|child_bbox| {
+                let cell = child_bbox.to_polygon();
+                let child_selected: Vec<T> = selected
+                    .iter()
+                    .filter(|&label| lines.get(label).unwrap().intersects(&cell))
+                    .cloned()
+                    .collect();
+                LineNode::build(&child_selected, lines, child_bbox, max_depth, depth + 1)
+            })
+            .collect();
+        LineNode {
+            children,
+            labels: vec![],
+            bbox,
+        }
+    }
+
+    /// Adds the labels of the lines crossing the leaves that intersect the window to `labels`.
+    fn collect_labels<'a>(&'a self, window: &Rect, labels: &mut HashSet<&'a T>) {
+        if !self.bbox.intersects(window) {
+            return;
+        }
+        if self.children.is_empty() {
+            labels.extend(self.labels.iter());
+        } else {
+            self.children
+                .iter()
+                .for_each(|child| child.collect_labels(window, labels));
+        }
+    }
+
+    /// Returns the number of leaf nodes under this node.
+    fn size(&self) -> usize {
+        if self.children.is_empty() {
+            1
+        } else {
+            self.children.iter().map(|child| child.size()).sum()
+        }
+    }
+}
@@ -1,77 +1,307 @@
-use std::{net::SocketAddr, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
 
-use axum::{extract::Query, routing::get, Router};
-use datasets::{
-    load_or_compute_country_label_tree, load_or_compute_province_label_tree, lazy_download_map_data,
+use axum::{
+    extract::{self, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
 };
 use geo::Point;
-
-mod datasets;
-mod labeling;
+use pinpointer::datasets::{lazy_download_map_data, DatasetRegistry};
 
 use serde::Deserialize;
-
-use crate::labeling::LabeledPartitionTree;
+use serde_json::json;
 
 #[derive(Deserialize, Debug)]
 struct LatLon {
     lat: f64,
     lon: f64,
+    /// If set, the response also includes the matched region's feature properties.
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// The query arguments accepted alongside coordinates given as path segments.
+#[derive(Deserialize, Debug)]
+struct Verbosity {
+    /// If set, the response also includes the matched region's feature properties.
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Counts how many queries resolved to each label.
+#[derive(Default)]
+struct QueryCounts {
+    counts: RwLock<HashMap<String, AtomicU64>>,
+}
+
+impl QueryCounts {
+    fn increment(&self, label: &str) {
+        if let Some(count) = self.counts.read().unwrap().get(label) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.counts
+            .write()
+            .unwrap()
+            .entry(label.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HashMap<String, u64> {
+        self.counts
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(label, count)| (label.clone(), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// The state shared by all request handlers.
+struct AppState {
+    registry: DatasetRegistry,
+    query_counts: Option<HashMap<String, QueryCounts>>,
+}
+
+async fn lookup(
+    extract::Path(dataset): extract::Path<String>,
+    Query(lat_lon): Query<LatLon>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let label = label_point(&state, &dataset, lat_lon.lat, lat_lon.lon)?;
+    label_response(&state, &dataset, label, lat_lon.verbose)
 }
 
-async fn lat_lon_to_label(
-    lat_lon: LatLon,
-    label_tree: Arc<LabeledPartitionTree<String>>,
-) -> String {
-    label_tree
-        .label(&Point::new(lat_lon.lon, lat_lon.lat))
-        .unwrap_or(String::from("-99"))
+async fn country_at(
+    extract::Path(lat_lon): extract::Path<(String, String)>,
+    Query(verbosity): Query<Verbosity>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let (lat, lon) = parse_lat_lon(&lat_lon)?;
+    let label = label_point(&state, "country", lat, lon)?;
+    label_response(&state, "country", label, verbosity.verbose)
+}
+
+async fn province_at(
+    extract::Path(lat_lon): extract::Path<(String, String)>,
+    Query(verbosity): Query<Verbosity>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let (lat, lon) = parse_lat_lon(&lat_lon)?;
+    let label = label_point(&state, "province", lat, lon)?;
+    label_response(&state, "province", label, verbosity.verbose)
+}
+
+/// Responds with a label, or with JSON containing the label and its region's feature properties
+/// if `verbose` is set.
+fn label_response(
+    state: &AppState,
+    dataset: &str,
+    label: String,
+    verbose: bool,
+) -> Result<Response, StatusCode> {
+    if !verbose {
+        return Ok(label.into_response());
+    }
+
+    let properties = state
+        .registry
+        .properties(dataset)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .get(&label);
+    Ok(Json(json!({ "label": label, "properties": properties })).into_response())
+}
+
+/// Parses latitude and longitude path segments, rejecting malformed segments with a 400.
+fn parse_lat_lon((lat, lon): &(String, String)) -> Result<(f64, f64), StatusCode> {
+    match (lat.parse(), lon.parse()) {
+        (Ok(lat), Ok(lon)) => Ok((lat, lon)),
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// Labels a point using the named dataset's tree, returning "-99" if no region contains it.
+fn label_point(state: &AppState, dataset: &str, lat: f64, lon: f64) -> Result<String, StatusCode> {
+    if !state.registry.contains(dataset) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let label_tree = state
+        .registry
+        .tree(dataset)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let label = label_tree
+        .label(&Point::new(lon, lat))
+        .unwrap_or(String::from("-99"));
+    if let Some(counts) = state
+        .query_counts
+        .as_ref()
+        .and_then(|query_counts| query_counts.get(dataset))
+    {
+        counts.increment(&label);
+    }
+    Ok(label)
+}
+
+async fn get_query_counts(
+    Extension(state): Extension<Arc<AppState>>,
+) -> Json<HashMap<String, HashMap<String, u64>>> {
+    Json(
+        state
+            .query_counts
+            .iter()
+            .flatten()
+            .map(|(dataset, counts)| (dataset.clone(), counts.snapshot()))
+            .collect(),
+    )
+}
+
+/// Returns the value following the given flag in the command line arguments, if any.
+fn arg_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1).cloned())
+}
+
+/// Labels the known points in a self-test file, printing any mismatches, and returns the fraction
+/// of points that were labeled as expected.
+///
+/// Each non-empty line of the file has the form `dataset,lat,lon,expected`, where `expected` is
+/// "-99" for points that shouldn't be in any region; lines starting with `#` are ignored.
+fn run_self_test(
+    registry: &DatasetRegistry,
+    path: &Path,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut total = 0;
+    let mut matches = 0;
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let (dataset, lat, lon, expected) = match fields[..] {
+            [dataset, lat, lon, expected] => {
+                (dataset, lat.parse::<f64>()?, lon.parse::<f64>()?, expected)
+            }
+            _ => {
+                return Err(format!(
+                    "line {} of {} should have the form dataset,lat,lon,expected",
+                    line_number + 1,
+                    path.display()
+                )
+                .into())
+            }
+        };
+        let label = registry
+            .tree(dataset)?
+            .label(&Point::new(lon, lat))
+            .unwrap_or(String::from("-99"));
+        total += 1;
+        if label == expected {
+            matches += 1;
+        } else {
+            println!(
+                "Self-test mismatch: {dataset} at ({lat}, {lon}) is {label}, expected {expected}"
+            );
+        }
+    }
+    if total == 0 {
+        return Err(format!("{} contains no self-test points", path.display()).into());
+    }
+    Ok(matches as f64 / total as f64)
 }
 
 #[tokio::main]
 async fn main() {
+    // per-label query counting is opt-in since it adds a little work to every request
+    let count_queries = std::env::args().any(|arg| arg == "--count-queries");
+    let self_test = arg_value("--self-test");
+    let self_test_threshold: f64 = arg_value("--self-test-threshold")
+        .map(|threshold| {
+            threshold
+                .parse()
+                .expect("Could not parse --self-test-threshold.")
+        })
+        .unwrap_or(1.0);
+
     lazy_download_map_data().expect("Could not load or download map data.");
 
-    let country_label_tree = load_or_compute_country_label_tree(
-        Path::new("data"),
-        Path::new("data\\ne_10m_admin_0_countries_lakes.geojson"),
-        6
-    );
-    let country_label_tree_arc = Arc::new(country_label_tree);
-
-    let province_label_tree = load_or_compute_province_label_tree(
-        Path::new("data"),
-        Path::new("data\\ne_10m_admin_1_states_provinces_lakes.geojson"),
-        6
-    );
-    let province_label_tree_arc = Arc::new(province_label_tree);
-
-    let app = Router::new()
-        .route(
-            "/lat_lon_to_country",
-            get(move |lat_lon: Query<LatLon>| {
-                lat_lon_to_label(
-                    LatLon {
-                        lat: lat_lon.lat,
-                        lon: lat_lon.lon,
-                    },
-                    country_label_tree_arc.clone(),
-                )
-            }),
+    let data_dir = Path::new("data");
+    let mut registry = DatasetRegistry::new(data_dir);
+    registry
+        .register(
+            "country",
+            &data_dir.join("ne_10m_admin_0_countries_lakes.geojson"),
+            "ISO_A2",
+            6,
         )
-        .route(
-            "/lat_lon_to_province",
-            get(move |lat_lon: Query<LatLon>| {
-                lat_lon_to_label(
-                    LatLon {
-                        lat: lat_lon.lat,
-                        lon: lat_lon.lon,
-                    },
-                    province_label_tree_arc.clone(),
-                )
-            }),
+        .register(
+            "province",
+            &data_dir.join("ne_10m_admin_1_states_provinces_lakes.geojson"),
+            "iso_3166_2",
+            6,
         );
 
+    // load every tree and its feature properties up front so the first request to each dataset
+    // isn't slow; loading them lazily would also block the async runtime while parsing GeoJSON
+    for name in registry.names() {
+        registry
+            .tree(name)
+            .unwrap_or_else(|e| panic!("Could not load or compute the {name} label tree: {e}"));
+        registry
+            .properties(name)
+            .unwrap_or_else(|e| panic!("Could not load the {name} feature properties: {e}"));
+    }
+
+    // labeling known points warms the trees and catches the wrong data being loaded before any
+    // traffic is served
+    if let Some(path) = self_test {
+        let accuracy = run_self_test(&registry, Path::new(&path))
+            .unwrap_or_else(|e| panic!("Could not run the self-test: {e}"));
+        println!("Self-test accuracy: {:.2}%", accuracy * 100.0);
+        if accuracy < self_test_threshold {
+            panic!(
+                "Self-test accuracy {:.2}% is below the threshold of {:.2}%.",
+                accuracy * 100.0,
+                self_test_threshold * 100.0
+            );
+        }
+    }
+
+    let query_counts: Option<HashMap<String, QueryCounts>> = count_queries.then(|| {
+        registry
+            .names()
+            .into_iter()
+            .map(|name| (name.to_string(), QueryCounts::default()))
+            .collect()
+    });
+
+    let mut app = Router::new()
+        .route("/lookup/:dataset", get(lookup))
+        .route("/country/:lat/:lon", get(country_at))
+        .route("/province/:lat/:lon", get(province_at));
+    if count_queries {
+        app = app.route("/query_counts", get(get_query_counts));
+    }
+    let app = app.layer(Extension(Arc::new(AppState {
+        registry,
+        query_counts,
+    })));
+
     let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
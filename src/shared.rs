@@ -0,0 +1,58 @@
+//! Lock-free sharing of a `LabeledPartitionTree` that can be replaced while it is being queried.
+
+use std::{hash::Hash, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+use crate::labeling::{LabeledPartitionTree, QueryPoint};
+
+/// A `LabeledPartitionTree` that can be queried from many threads while it is being replaced.
+///
+/// Readers always see a consistent snapshot of the tree and never block. Writers build a new tree
+/// and publish it atomically; queries that started before a publish finish on the old tree, which
+/// is freed once the last of them completes.
+pub struct SharedTree<T: Eq + Hash> {
+    current: ArcSwap<LabeledPartitionTree<T>>,
+}
+
+impl<T: Clone + Eq + Hash> SharedTree<T> {
+    /// Creates a shared tree that initially serves the given tree.
+    pub fn new(tree: LabeledPartitionTree<T>) -> Self {
+        SharedTree {
+            current: ArcSwap::from_pointee(tree),
+        }
+    }
+
+    /// Returns the label of the partition that contains the given point in the current tree.
+    ///
+    /// # Arguments
+    /// * `point` - The point to check.
+    pub fn label(&self, point: impl QueryPoint) -> Option<T> {
+        self.current.load().label(point)
+    }
+
+    /// Returns a snapshot of the current tree, which stays valid even if a new tree is published.
+    /// This is useful for running several queries against the same version of the tree.
+    pub fn snapshot(&self) -> Arc<LabeledPartitionTree<T>> {
+        self.current.load_full()
+    }
+
+    /// Atomically replaces the current tree.
+    ///
+    /// # Arguments
+    /// * `tree` - The tree to serve from now on.
+    pub fn publish(&self, tree: LabeledPartitionTree<T>) {
+        self.current.store(Arc::new(tree));
+    }
+
+    /// Atomically replaces the current tree with one derived from it.
+    ///
+    /// If another writer publishes while `f` is running, `f` is called again on the newly published
+    /// tree, so no update is lost.
+    ///
+    /// # Arguments
+    /// * `f` - Computes the new tree from the current one.
+    pub fn update(&self, f: impl Fn(&LabeledPartitionTree<T>) -> LabeledPartitionTree<T>) {
+        self.current.rcu(|current| f(current));
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
+use pinpointer::labeling::{LabeledPartitionTree, TreeBuilder};
+
+/// Builds an unclipped tree with a circular region with many vertices and a small square region
+/// in a hole at its center. The cells around the hole are split down to the maximum depth, so
+/// some of the leaves there are covered by the circle but still store the whole circle.
+fn circle_tree() -> LabeledPartitionTree<String> {
+    let ring: Vec<(f64, f64)> = (0..=256)
+        .map(|i| {
+            let angle = i as f64 / 256.0 * std::f64::consts::TAU;
+            (10.0 + 8.0 * angle.cos(), 10.0 + 8.0 * angle.sin())
+        })
+        .collect();
+    let mut polygons = HashMap::new();
+    let hole = Rect::new(Point::new(9.4, 9.4), Point::new(10.6, 10.6));
+    polygons.insert(
+        String::from("circle"),
+        MultiPolygon::new(vec![Polygon::new(
+            LineString::from(ring),
+            vec![hole.to_polygon().exterior().clone()],
+        )]),
+    );
+    polygons.insert(
+        String::from("square"),
+        MultiPolygon::from(Rect::new(Point::new(9.6, 9.6), Point::new(10.4, 10.4))),
+    );
+    TreeBuilder::new()
+        .root(Rect::new(Point::new(0.3, 0.3), Point::new(20.3, 20.3)))
+        .max_depth(4)
+        .clip(false)
+        .build(&polygons)
+}
+
+#[test]
+fn compact_collapses_leaves_covered_by_their_region() {
+    let tree = circle_tree();
+    let mut compacted = tree.clone();
+    compacted.compact();
+    assert!(compacted.total_vertices() < tree.total_vertices());
+    // the grid is offset from the cell boundaries, which no cell contains
+    for i in 0..40 {
+        for j in 0..40 {
+            let point = Point::new(0.41 + i as f64 * 0.49, 0.43 + j as f64 * 0.497);
+            assert_eq!(compacted.label(&point), tree.label(&point), "{point:?}");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
+use pinpointer::{
+    fixed::{FixedCoord, FixedLabeledPartitionTree},
+    labeling::TreeBuilder,
+};
+
+/// Returns a square and a triangle, with the square spanning the origin.
+fn regions() -> HashMap<String, MultiPolygon> {
+    let mut polygons = HashMap::new();
+    polygons.insert(
+        String::from("square"),
+        MultiPolygon::from(Rect::new(Point::new(-5.0, -5.0), Point::new(5.0, 5.0))),
+    );
+    polygons.insert(
+        String::from("triangle"),
+        MultiPolygon::new(vec![Polygon::new(
+            LineString::from(vec![(20.0, 10.0), (40.0, 10.0), (30.0, 30.0), (20.0, 10.0)]),
+            vec![],
+        )]),
+    );
+    polygons
+}
+
+#[test]
+fn fixed_point_trees_label_like_floating_point_trees() {
+    let polygons = regions();
+    let float = TreeBuilder::new().max_depth(5).build(&polygons);
+    let converted = FixedLabeledPartitionTree::from_tree(&float);
+    let built = FixedLabeledPartitionTree::from_labeled_polygons(&polygons, 5);
+    // the grid is offset from the cell boundaries and polygon edges, which no cell contains
+    for i in 0..30 {
+        for j in 0..30 {
+            let (lon, lat) = (-9.87 + i as f64 * 1.71, -9.93 + j as f64 * 1.43);
+            let expected = float.label(&Point::new(lon, lat));
+            let point = FixedCoord::from_degrees(lon, lat);
+            assert_eq!(converted.label(point), expected, "{lon}, {lat}");
+            assert_eq!(built.label(point), expected, "{lon}, {lat}");
+        }
+    }
+}
+
+#[test]
+fn cell_boundaries_are_unlabeled_like_floating_point_trees() {
+    let polygons = regions();
+    let float = TreeBuilder::new().max_depth(5).build(&polygons);
+    let converted = FixedLabeledPartitionTree::from_tree(&float);
+    let built = FixedLabeledPartitionTree::from_labeled_polygons(&polygons, 5);
+    // the root's children meet at the prime meridian, which runs through the square
+    let (lon, lat) = (0.0, 1.3);
+    assert_eq!(float.label(&Point::new(lon, lat)), None);
+    assert_eq!(converted.label(FixedCoord::from_degrees(lon, lat)), None);
+    assert_eq!(built.label(FixedCoord::from_degrees(lon, lat)), None);
+}
@@ -0,0 +1,73 @@
+use geo::{Contains, LineString, MultiPolygon, Point, Polygon};
+use pinpointer::geometry::buffer_multi_polygon;
+
+/// Builds a 3x3 "U" with a 1x2 notch cut into the middle of its top edge, so that the region has
+/// two concave corners at the bottom of the notch.
+fn u_shape() -> MultiPolygon {
+    MultiPolygon::new(vec![Polygon::new(
+        LineString::from(vec![
+            (0.0, 0.0),
+            (3.0, 0.0),
+            (3.0, 3.0),
+            (2.0, 3.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 3.0),
+            (0.0, 3.0),
+            (0.0, 0.0),
+        ]),
+        vec![],
+    )])
+}
+
+#[test]
+fn buffering_a_concave_region_keeps_the_region() {
+    let buffered = buffer_multi_polygon(&u_shape(), 0.25);
+    for point in [(0.5, 0.5), (2.5, 2.5), (0.5, 2.9), (1.5, 0.9), (0.99, 1.01)] {
+        assert!(
+            buffered.contains(&Point::from(point)),
+            "{:?} should stay inside the buffered region",
+            point
+        );
+    }
+}
+
+#[test]
+fn buffering_a_concave_region_grows_into_its_notch() {
+    let buffered = buffer_multi_polygon(&u_shape(), 0.25);
+    // within `distance` of the notch's walls, its floor, and the concave corners between them
+    for point in [(1.1, 2.0), (1.9, 2.0), (1.5, 1.1), (1.2, 1.2), (1.8, 1.2)] {
+        assert!(
+            buffered.contains(&Point::from(point)),
+            "{:?} should be covered by the buffer",
+            point
+        );
+    }
+    // the middle of the notch is farther than `distance` from every wall
+    for point in [(1.5, 2.0), (1.5, 1.5)] {
+        assert!(
+            !buffered.contains(&Point::from(point)),
+            "{:?} should stay outside the buffer",
+            point
+        );
+    }
+}
+
+#[test]
+fn buffering_a_concave_region_stays_within_distance() {
+    let buffered = buffer_multi_polygon(&u_shape(), 0.25);
+    for point in [(-0.1, 1.5), (3.2, 0.5), (1.5, -0.2), (3.1, 3.1)] {
+        assert!(
+            buffered.contains(&Point::from(point)),
+            "{:?} should be covered by the buffer",
+            point
+        );
+    }
+    for point in [(-0.3, 1.5), (3.5, 1.5), (1.5, -0.3), (1.5, 3.3)] {
+        assert!(
+            !buffered.contains(&Point::from(point)),
+            "{:?} should stay outside the buffer",
+            point
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
+use pinpointer::labeling::{LabeledPartitionTree, TreeBuilder};
+
+/// Builds a tree with a single 10x10 "land" square with a 6x6 "lake" hole in the middle.
+fn lake_tree() -> LabeledPartitionTree<String> {
+    let land = Polygon::new(
+        LineString::from(vec![
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ]),
+        vec![LineString::from(vec![
+            (2.0, 2.0),
+            (8.0, 2.0),
+            (8.0, 8.0),
+            (2.0, 8.0),
+            (2.0, 2.0),
+        ])],
+    );
+    let mut polygons = HashMap::new();
+    polygons.insert(String::from("land"), MultiPolygon::new(vec![land]));
+    // the root is offset from the sample grid so that no sample point lies on a cell boundary,
+    // where `Rect::contains` would exclude it from every cell
+    TreeBuilder::new()
+        .root(Rect::new(Point::new(-0.3, -0.3), Point::new(10.7, 10.7)))
+        .max_depth(3)
+        .build(&polygons)
+}
+
+#[test]
+fn points_in_holes_are_unlabeled() {
+    let tree = lake_tree();
+    assert_eq!(tree.label(&Point::new(5.0, 5.0)), None);
+    assert_eq!(
+        tree.label(&Point::new(1.0, 5.0)),
+        Some(String::from("land"))
+    );
+}
+
+#[test]
+fn area_histogram_does_not_count_holes() {
+    let tree = lake_tree();
+    let area = Rect::new(Point::new(0.0, 0.0), Point::new(10.0, 10.0));
+    // a 10x10 grid of cell centers, 6x6 of which fall in the lake
+    let histogram = tree.area_label_histogram(&area, 100);
+    assert_eq!(histogram.get("land"), Some(&64));
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use geo::{MultiPolygon, Point, Rect};
+use pinpointer::{labelers::CachingLabeler, labeling::TreeBuilder};
+
+#[test]
+fn cached_points_near_the_origin_are_labeled() {
+    let square = Rect::new(Point::new(-5.0, -5.0), Point::new(5.0, 5.0));
+    let mut polygons = HashMap::new();
+    polygons.insert(String::from("square"), MultiPolygon::from(square));
+    let tree = TreeBuilder::new().max_depth(4).build(&polygons);
+    let labeler = CachingLabeler::new(tree, 0.1, 16);
+
+    // (0, 0) lies on the boundary of the root's children, so a grid point there is unlabeled
+    assert_eq!(
+        labeler.label(&Point::new(0.01, 0.02)),
+        Some(String::from("square"))
+    );
+    // the second query hits the cache entry of the first
+    assert_eq!(
+        labeler.label(&Point::new(0.03, 0.04)),
+        Some(String::from("square"))
+    );
+    assert_eq!(
+        labeler.label(&Point::new(-0.02, -0.01)),
+        Some(String::from("square"))
+    );
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use geo::{Point, Rect};
+use pinpointer::{datasets::load_countries, labeling::LabeledPartitionTree};
+
+/// Builds a small country tree from the simplified borders in `tests/fixtures/landmarks.geojson`.
+fn landmark_tree() -> LabeledPartitionTree<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/landmarks.geojson");
+    let countries = load_countries(&path).unwrap();
+    LabeledPartitionTree::from_labeled_polygons(
+        &countries.keys().cloned().collect(),
+        &countries,
+        Rect::new(Point::new(-180.0, 90.0), Point::new(180.0, -90.0)),
+        4,
+        0,
+    )
+}
+
+/// Labels a coordinate written in the usual (latitude, longitude) order.
+fn label_lat_lon(tree: &LabeledPartitionTree<String>, lat: f64, lon: f64) -> Option<String> {
+    tree.label(&Point::new(lon, lat))
+}
+
+#[test]
+fn paris_is_in_france() {
+    let tree = landmark_tree();
+    assert_eq!(label_lat_lon(&tree, 48.85, 2.35), Some(String::from("FR")));
+}
+
+#[test]
+fn new_york_is_in_the_united_states() {
+    let tree = landmark_tree();
+    assert_eq!(label_lat_lon(&tree, 40.71, -74.0), Some(String::from("US")));
+}
+
+#[test]
+fn sydney_is_in_australia() {
+    let tree = landmark_tree();
+    assert_eq!(label_lat_lon(&tree, -33.87, 151.2), Some(String::from("AU")));
+}
+
+#[test]
+fn open_ocean_is_unlabeled() {
+    let tree = landmark_tree();
+    // a point in the South Atlantic that doesn't lie on a cell boundary, where nothing is labeled
+    assert_eq!(label_lat_lon(&tree, -10.3, -31.7), None);
+}
+
+#[test]
+fn swapped_axes_do_not_match() {
+    let tree = landmark_tree();
+    // Paris with latitude and longitude swapped lands off the coast of Somalia.
+    assert_eq!(label_lat_lon(&tree, 2.35, 48.85), None);
+}
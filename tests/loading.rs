@@ -0,0 +1,92 @@
+use std::{fs, path::Path};
+
+use geo::Point;
+use geojson::{Feature, FeatureCollection, Geometry, Value};
+use pinpointer::{
+    datasets::{
+        labeled_polygons_from_feature_collection,
+        labeled_polygons_from_feature_collection_with_options, load_labeled_collection_polygons,
+        LoadOptions, NonFinitePolicy,
+    },
+    labeling::TreeBuilder,
+};
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+#[test]
+fn unicode_labels_round_trip_through_the_tree() {
+    let provinces = load_labeled_collection_polygons(&fixture("unicode.geojson"), "name").unwrap();
+    let tree = TreeBuilder::new().max_depth(4).build(&provinces);
+    assert_eq!(
+        tree.label(&Point::new(2.35, 48.85)),
+        Some(String::from("Île-de-France"))
+    );
+    // this label is written with a JSON escape in the fixture
+    assert_eq!(
+        tree.label(&Point::new(5.0, 47.3)),
+        Some(String::from("Bourgogne-Franche-Comté"))
+    );
+}
+
+#[test]
+fn features_without_geometry_are_skipped() {
+    let provinces = load_labeled_collection_polygons(&fixture("unicode.geojson"), "name").unwrap();
+    assert_eq!(provinces.len(), 2);
+    assert!(!provinces.contains_key("Nowhere"));
+}
+
+#[test]
+fn invalid_utf8_is_reported_with_its_position() {
+    let path = std::env::temp_dir().join("pinpointer_invalid_utf8.geojson");
+    let contents = b"{\n  \"type\": \"FeatureCollection\",\n  \"features\": [\xff]\n}\n";
+    fs::write(&path, contents).unwrap();
+    let error = load_labeled_collection_polygons(&path, "name")
+        .err()
+        .unwrap()
+        .to_string();
+    fs::remove_file(&path).unwrap();
+    assert!(error.contains("line 3, column 16"), "{error}");
+}
+
+/// Builds a feature with a square polygon and the given name, with one vertex replaced by `vertex`.
+fn square_feature(name: &str, vertex: Vec<f64>) -> Feature {
+    let ring = vec![vec![0.0, 0.0], vec![1.0, 0.0], vertex, vec![0.0, 1.0], vec![0.0, 0.0]];
+    let mut properties = geojson::JsonObject::new();
+    properties.insert(String::from("name"), name.into());
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(Value::Polygon(vec![ring]))),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+#[test]
+fn non_finite_coordinates_are_reported() {
+    let collection = FeatureCollection {
+        bbox: None,
+        features: vec![
+            square_feature("good", vec![1.0, 1.0]),
+            square_feature("bad", vec![f64::NAN, 1.0]),
+        ],
+        foreign_members: None,
+    };
+    let error = labeled_polygons_from_feature_collection(&collection, "name")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("feature 1 (\"bad\")"), "{error}");
+
+    let options = LoadOptions {
+        non_finite: NonFinitePolicy::Skip,
+        ..LoadOptions::default()
+    };
+    let polygons =
+        labeled_polygons_from_feature_collection_with_options(&collection, "name", &options)
+            .unwrap();
+    assert_eq!(polygons.len(), 1);
+    assert!(polygons.contains_key("good"));
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use geo::{MultiPolygon, Point, Rect};
+use pinpointer::labeling::{LabeledPartitionTree, QueryContext, TreeBuilder};
+
+/// Builds a tree with a single square region, over a root cell shared by every such tree.
+fn square_tree(label: &str, min: (f64, f64), max: (f64, f64)) -> LabeledPartitionTree<String> {
+    let mut polygons = HashMap::new();
+    polygons.insert(String::from(label), MultiPolygon::from(Rect::new(min, max)));
+    TreeBuilder::new()
+        .root(Rect::new(Point::new(0.3, 0.3), Point::new(20.3, 20.3)))
+        .max_depth(3)
+        .build(&polygons)
+}
+
+#[test]
+fn overlapping_merged_trees_are_all_searched() {
+    let merged = LabeledPartitionTree::merge(vec![
+        square_tree("a", (1.0, 1.0), (5.0, 5.0)),
+        square_tree("b", (12.0, 12.0), (15.0, 15.0)),
+    ]);
+    let a = Point::new(2.1, 3.7);
+    let b = Point::new(13.1, 13.7);
+    let ocean = Point::new(8.1, 8.7);
+
+    // the first tree contains every point, but only labels `a`
+    assert_eq!(merged.label(&a), Some(String::from("a")));
+    assert_eq!(merged.label(&b), Some(String::from("b")));
+    assert_eq!(merged.label(&ocean), None);
+
+    let mut ctx = QueryContext::new();
+    for point in [a, b, ocean, b, a] {
+        assert_eq!(
+            merged.label_with_ctx(&point, &mut ctx),
+            merged.label(&point)
+        );
+    }
+}
+
+#[test]
+fn earlier_merged_trees_take_precedence() {
+    let merged = LabeledPartitionTree::merge(vec![
+        square_tree("first", (1.0, 1.0), (10.0, 10.0)),
+        square_tree("second", (5.0, 5.0), (15.0, 15.0)),
+    ]);
+    assert_eq!(
+        merged.label(&Point::new(7.1, 7.7)),
+        Some(String::from("first"))
+    );
+    assert_eq!(
+        merged.label(&Point::new(12.1, 12.7)),
+        Some(String::from("second"))
+    );
+}
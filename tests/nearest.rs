@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
+use pinpointer::labeling::{DistanceMetric, LabeledPartitionTree, TreeBuilder};
+
+/// Builds a depth 6 world tree with two small islands far apart.
+fn island_tree() -> LabeledPartitionTree<String> {
+    let mut polygons = HashMap::new();
+    polygons.insert(
+        String::from("near"),
+        MultiPolygon::from(Rect::new(Point::new(10.3, 10.3), Point::new(11.3, 11.3))),
+    );
+    polygons.insert(
+        String::from("far"),
+        MultiPolygon::from(Rect::new(
+            Point::new(-120.3, -40.3),
+            Point::new(-119.3, -39.3),
+        )),
+    );
+    TreeBuilder::new().max_depth(6).build(&polygons)
+}
+
+#[test]
+fn nearest_respects_max_distance() {
+    let tree = island_tree();
+    let point = Point::new(7.3, 10.8);
+    assert_eq!(
+        tree.label_or_nearest(&point, 5.0, DistanceMetric::Euclidean),
+        Some(String::from("near"))
+    );
+    assert_eq!(
+        tree.label_or_nearest(&point, 2.0, DistanceMetric::Euclidean),
+        None
+    );
+}
+
+#[test]
+fn k_nearest_widens_the_search_to_distant_regions() {
+    let tree = island_tree();
+    let point = Point::new(7.3, 10.8);
+    let nearest: Vec<String> = tree
+        .k_nearest(&point, 2, DistanceMetric::Euclidean)
+        .into_iter()
+        .map(|(label, _)| label)
+        .collect();
+    assert_eq!(nearest, vec![String::from("near"), String::from("far")]);
+    assert_eq!(
+        tree.k_nearest(&point, 5, DistanceMetric::Haversine).len(),
+        2
+    );
+}
+
+#[test]
+fn haversine_distance_scales_longitudes_at_high_latitudes() {
+    // an L-shaped region whose eastern bar is 3.9 degrees of longitude away from the point and
+    // whose northern bar is 1.5 degrees of latitude away; at 70N the eastern bar is closer
+    let region = MultiPolygon::new(vec![Polygon::new(
+        LineString::from(vec![
+            (4.0, 69.0),
+            (4.0, 71.6),
+            (-1.0, 71.6),
+            (-1.0, 71.5),
+            (3.9, 71.5),
+            (3.9, 69.0),
+            (4.0, 69.0),
+        ]),
+        vec![],
+    )]);
+    let distance = DistanceMetric::Haversine.distance(&Point::new(0.0, 70.0), &region);
+    // about 148 km to the eastern bar, rather than about 167 km to the northern bar
+    assert!((distance - 148.3).abs() < 1.0, "{distance}");
+}
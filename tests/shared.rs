@@ -0,0 +1,79 @@
+#![cfg(feature = "shared")]
+
+use std::collections::HashMap;
+
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
+use pinpointer::labeling::{LabeledPartitionTree, TreeBuilder};
+use pinpointer::shared::SharedTree;
+
+/// Builds a tree with a single square region with the given label.
+fn square_tree(label: &str) -> LabeledPartitionTree<String> {
+    let square = Polygon::new(
+        LineString::from(vec![
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ]),
+        vec![],
+    );
+    let mut polygons = HashMap::new();
+    polygons.insert(String::from(label), MultiPolygon::new(vec![square]));
+    TreeBuilder::new()
+        .root(Rect::new(Point::new(-0.3, -0.3), Point::new(10.7, 10.7)))
+        .max_depth(3)
+        .build(&polygons)
+}
+
+#[test]
+fn published_trees_replace_the_current_tree() {
+    let shared = SharedTree::new(square_tree("old"));
+    let point = Point::new(5.1, 5.1);
+    assert_eq!(shared.label(point), Some(String::from("old")));
+
+    let snapshot = shared.snapshot();
+    shared.publish(square_tree("new"));
+    assert_eq!(shared.label(point), Some(String::from("new")));
+    // snapshots taken before a publish keep serving the old tree
+    assert_eq!(snapshot.label(point), Some(String::from("old")));
+}
+
+#[test]
+fn updates_derive_the_new_tree_from_the_current_one() {
+    let shared = SharedTree::new(square_tree("old"));
+    let point = Point::new(5.1, 5.1);
+    shared.update(|current| {
+        let label = current.label(point).unwrap();
+        square_tree(&format!("{label}+1"))
+    });
+    assert_eq!(shared.label(point), Some(String::from("old+1")));
+}
+
+#[test]
+fn readers_see_whole_trees_while_writers_publish() {
+    let shared = SharedTree::new(square_tree("0"));
+    let point = Point::new(5.1, 5.1);
+    std::thread::scope(|scope| {
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..1000 {
+                        let version: usize = shared.label(point).unwrap().parse().unwrap();
+                        // a single writer only ever publishes newer versions
+                        assert!(version >= last);
+                        last = version;
+                    }
+                })
+            })
+            .collect();
+        for version in 1..=20 {
+            shared.publish(square_tree(&version.to_string()));
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    });
+    assert_eq!(shared.label(point), Some(String::from("20")));
+}
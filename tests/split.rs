@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use geo::{MultiPolygon, Point, Rect};
+use pinpointer::labeling::{LabeledPartitionTree, SplitStrategy, TreeBuilder};
+
+/// Builds a tree over a high-latitude region, where degrees of longitude are much shorter than
+/// degrees of latitude, with the given split rule.
+fn arctic_tree(split: SplitStrategy) -> LabeledPartitionTree<String> {
+    let mut polygons = HashMap::new();
+    for (label, min, max) in [
+        ("a", (2.0, 62.0), (9.0, 71.0)),
+        ("b", (11.0, 66.0), (17.0, 78.0)),
+        ("c", (4.0, 74.0), (8.0, 79.0)),
+    ] {
+        polygons.insert(String::from(label), MultiPolygon::from(Rect::new(min, max)));
+    }
+    TreeBuilder::new()
+        .root(Rect::new(Point::new(0.0, 60.0), Point::new(20.0, 80.0)))
+        .max_depth(4)
+        .branching(split)
+        .build(&polygons)
+}
+
+#[test]
+fn latitude_aware_cells_are_closer_to_square_on_the_ground() {
+    let quadrants = arctic_tree(SplitStrategy::Quadrants).stats();
+    let latitude_aware = arctic_tree(SplitStrategy::LatitudeAware).stats();
+    assert!(
+        latitude_aware.mean_leaf_ground_aspect_ratio < quadrants.mean_leaf_ground_aspect_ratio,
+        "{latitude_aware:?} vs {quadrants:?}"
+    );
+}
+
+#[test]
+fn latitude_aware_trees_label_like_quadrant_trees() {
+    let quadrants = arctic_tree(SplitStrategy::Quadrants);
+    let latitude_aware = arctic_tree(SplitStrategy::LatitudeAware);
+    // the grid is offset from the cell boundaries and polygon edges, which no cell contains
+    for i in 0..20 {
+        for j in 0..20 {
+            let point = Point::new(0.37 + i as f64 * 0.97, 60.29 + j as f64 * 0.983);
+            assert_eq!(
+                latitude_aware.label(&point),
+                quadrants.label(&point),
+                "{point:?}"
+            );
+        }
+    }
+}